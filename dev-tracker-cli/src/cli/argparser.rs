@@ -1,33 +1,253 @@
-use chrono::offset::TimeZone;
-use chrono::{DateTime, Local, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+use chrono::{
+    DateTime, Datelike, Days, Duration, Months, NaiveDate, NaiveDateTime, NaiveTime, Utc, Weekday,
+};
+use chrono_tz::Tz;
 
 use super::errors::ArgParserError;
+use super::timezone::{DstPolicy, TimeContext};
 
-pub fn parse_datetime(arg: &str) -> Result<DateTime<Utc>, ArgParserError> {
-    let datetime = NaiveDateTime::parse_from_str(arg, "%Y-%m-%dT%H:%M")?;
+#[derive(Clone, Copy, Debug)]
+pub struct DateRange {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+}
 
-    let datetime = match Local.from_local_datetime(&datetime) {
-        chrono::LocalResult::Single(datetime) => datetime,
-        _ => panic!("Failed to convert local datetime into UTC datetime."),
-    };
+// A date expression is either a single point in time, or a span of whole days
+// from the start of `first` up to but not including the start of `last`.
+enum Parsed {
+    Instant(DateTime<Utc>),
+    Days(NaiveDate, NaiveDate),
+}
+
+/// A date or time from the command line.  The syntax is checked when the
+/// arguments are parsed, but the value depends on the timezone and daylight
+/// saving policy so it is only resolved once those are known.
+#[derive(Clone, Debug)]
+pub struct TimeArg(String);
+
+impl TimeArg {
+    /// The date and time, or the start of a day or named period.
+    pub fn datetime(&self, ctx: &TimeContext) -> Result<DateTime<Utc>, ArgParserError> {
+        match parse(&self.0, ctx)? {
+            Parsed::Instant(datetime) => Ok(datetime),
+            Parsed::Days(first, _) => Ok(ctx.start_of_day(first)),
+        }
+    }
 
-    let datetime: DateTime<Utc> = DateTime::from(datetime);
+    /// The date and time, or the end of a day or named period.  Used for the
+    /// end of a reporting period so that 'yesterday' includes all of yesterday.
+    pub fn end_date(&self, ctx: &TimeContext) -> Result<DateTime<Utc>, ArgParserError> {
+        match parse(&self.0, ctx)? {
+            Parsed::Instant(datetime) => Ok(datetime),
+            Parsed::Days(_, last) => Ok(ctx.start_of_day(last) - Duration::nanoseconds(1)),
+        }
+    }
 
-    Ok(datetime)
+    /// The range covered by a day or a named period such as 'this-month'.
+    pub fn range(&self, ctx: &TimeContext) -> Result<DateRange, ArgParserError> {
+        match parse(&self.0, ctx)? {
+            Parsed::Instant(_) => Err(ArgParserError::DateParserError(self.0.clone())),
+            Parsed::Days(first, last) => Ok(DateRange {
+                start: ctx.start_of_day(first),
+                end: ctx.start_of_day(last) - Duration::nanoseconds(1),
+            }),
+        }
+    }
 }
 
-pub fn parse_date(arg: &str) -> Result<DateTime<Utc>, ArgParserError> {
-    let date = NaiveDate::parse_from_str(arg, "%d-%m-%Y")?;
-    let time = NaiveTime::from_hms_opt(12, 0, 0)
-        .expect("should always be able to create a time of midday");
-    let datetime = NaiveDateTime::new(date, time);
+/// Checks a date and time, a day, or a named period.
+pub fn parse_datetime(arg: &str) -> Result<TimeArg, ArgParserError> {
+    parse(arg, &syntax_context())?;
+    Ok(TimeArg(arg.to_string()))
+}
+
+/// Checks a day or a named period.
+pub fn parse_range(arg: &str) -> Result<TimeArg, ArgParserError> {
+    let time = TimeArg(arg.to_string());
+    time.range(&syntax_context())?;
+    Ok(time)
+}
+
+// UTC has no daylight saving transitions, so checking against it only fails
+// for arguments that can never be parsed.
+fn syntax_context() -> TimeContext {
+    TimeContext::new(Some(Tz::UTC), DstPolicy::Reject)
+}
+
+pub fn parse_duration(arg: &str) -> Result<Duration, ArgParserError> {
+    let mut duration = Duration::zero();
+    let mut digits = String::new();
+
+    let mut normalised: String = arg
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    for (long, short) in [
+        ("seconds", "s"),
+        ("second", "s"),
+        ("secs", "s"),
+        ("sec", "s"),
+        ("minutes", "m"),
+        ("minute", "m"),
+        ("mins", "m"),
+        ("min", "m"),
+        ("hours", "h"),
+        ("hour", "h"),
+        ("hrs", "h"),
+        ("hr", "h"),
+        ("days", "d"),
+        ("day", "d"),
+    ] {
+        normalised = normalised.replace(long, short);
+    }
+
+    for c in normalised.chars() {
+        if c.is_ascii_digit() {
+            digits.push(c);
+            continue;
+        }
+
+        let Ok(value) = digits.parse::<i64>() else {
+            return Err(ArgParserError::DurationParserError(arg.to_string()));
+        };
+        duration += match c {
+            'd' => Duration::days(value),
+            'h' => Duration::hours(value),
+            'm' => Duration::minutes(value),
+            's' => Duration::seconds(value),
+            _ => return Err(ArgParserError::DurationParserError(arg.to_string())),
+        };
+        digits.clear();
+    }
+
+    if !digits.is_empty() || duration.is_zero() {
+        return Err(ArgParserError::DurationParserError(arg.to_string()));
+    }
+
+    Ok(duration)
+}
+
+fn parse(arg: &str, ctx: &TimeContext) -> Result<Parsed, ArgParserError> {
+    let now = ctx.now();
+    let today = now.date_naive();
+    let trimmed = arg.trim();
+    let normalised = trimmed.to_lowercase();
+    let words: Vec<&str> = normalised.split_whitespace().collect();
+
+    if normalised == "now" {
+        return Ok(Parsed::Instant(now.with_timezone(&Utc)));
+    }
 
-    let datetime = match Local.from_local_datetime(&datetime) {
-        chrono::LocalResult::Single(datetime) => datetime,
-        _ => panic!("Failed to convert local datetime into UTC datetime."),
+    if let Some(duration) = normalised.strip_suffix("ago") {
+        let duration = parse_duration(duration)?;
+        return Ok(Parsed::Instant(now.with_timezone(&Utc) - duration));
+    }
+
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(trimmed) {
+        return Ok(Parsed::Instant(datetime.with_timezone(&Utc)));
+    }
+
+    for format in [
+        "%Y-%m-%dT%H:%M:%S",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S",
+        "%Y-%m-%d %H:%M",
+    ] {
+        if let Ok(datetime) = NaiveDateTime::parse_from_str(trimmed, format) {
+            return Ok(Parsed::Instant(ctx.resolve(datetime)?));
+        }
+    }
+
+    for format in ["%Y-%m-%d", "%d-%m-%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(trimmed, format) {
+            return Ok(Parsed::Days(date, next_day(date)));
+        }
+    }
+
+    if let Some(time) = parse_time(&normalised) {
+        return Ok(Parsed::Instant(ctx.resolve(today.and_time(time))?));
+    }
+
+    if let Some((first, last)) = parse_period(&words.join("-"), today) {
+        return Ok(Parsed::Days(first, last));
+    }
+
+    // A day, optionally followed by a time of day, for example 'yesterday',
+    // 'monday 09:30', or 'last friday 14:00'.
+    let (day, rest) = match words.as_slice() {
+        ["last", weekday, rest @ ..] => match parse_weekday(weekday) {
+            Some(weekday) => (Some(previous_weekday(today - Days::new(1), weekday)), rest),
+            None => (None, rest),
+        },
+        [word, rest @ ..] => (parse_day(word, today), rest),
+        [] => (None, &[][..]),
+    };
+    let Some(day) = day else {
+        return Err(ArgParserError::DateParserError(arg.to_string()));
     };
 
-    let datetime: DateTime<Utc> = DateTime::from(datetime);
+    match rest {
+        [] => Ok(Parsed::Days(day, next_day(day))),
+        [time] => match parse_time(time) {
+            Some(time) => Ok(Parsed::Instant(ctx.resolve(day.and_time(time))?)),
+            None => Err(ArgParserError::TimeParserError(time.to_string())),
+        },
+        _ => Err(ArgParserError::DateParserError(arg.to_string())),
+    }
+}
+
+fn parse_time(arg: &str) -> Option<NaiveTime> {
+    ["%H:%M:%S", "%H:%M", "%I:%M%P"]
+        .iter()
+        .find_map(|format| NaiveTime::parse_from_str(arg, format).ok())
+}
+
+fn parse_day(word: &str, today: NaiveDate) -> Option<NaiveDate> {
+    match word {
+        "today" => Some(today),
+        "yesterday" => today.checked_sub_days(Days::new(1)),
+        "tomorrow" => today.checked_add_days(Days::new(1)),
+        word => parse_weekday(word).map(|weekday| previous_weekday(today, weekday)),
+    }
+}
+
+fn parse_weekday(word: &str) -> Option<Weekday> {
+    match word {
+        "monday" | "mon" => Some(Weekday::Mon),
+        "tuesday" | "tue" | "tues" => Some(Weekday::Tue),
+        "wednesday" | "wed" => Some(Weekday::Wed),
+        "thursday" | "thu" | "thurs" => Some(Weekday::Thu),
+        "friday" | "fri" => Some(Weekday::Fri),
+        "saturday" | "sat" => Some(Weekday::Sat),
+        "sunday" | "sun" => Some(Weekday::Sun),
+        _ => None,
+    }
+}
+
+// The most recent `weekday` on or before `day`.
+fn previous_weekday(day: NaiveDate, weekday: Weekday) -> NaiveDate {
+    let days_back = (7 + day.weekday().num_days_from_monday() - weekday.num_days_from_monday()) % 7;
+    day - Days::new(days_back as u64)
+}
+
+// Named periods, weeks start on Monday.
+fn parse_period(name: &str, today: NaiveDate) -> Option<(NaiveDate, NaiveDate)> {
+    let week = previous_weekday(today, Weekday::Mon);
+    let month = today.with_day(1)?;
+    let year = month.with_month(1)?;
+
+    match name {
+        "this-week" => Some((week, week + Days::new(7))),
+        "last-week" => Some((week - Days::new(7), week)),
+        "this-month" => Some((month, month + Months::new(1))),
+        "last-month" => Some((month - Months::new(1), month)),
+        "this-year" => Some((year, year + Months::new(12))),
+        "last-year" => Some((year - Months::new(12), year)),
+        _ => None,
+    }
+}
 
-    Ok(datetime)
+fn next_day(date: NaiveDate) -> NaiveDate {
+    date + Days::new(1)
 }
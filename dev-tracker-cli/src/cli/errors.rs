@@ -1,22 +1,45 @@
 use std::fmt::Display;
 
+use dev_tracker_core::Error;
+use rusqlite::ffi;
+
 #[derive(Debug)]
 pub enum ArgParserError {
     ChronoParserError(chrono::format::ParseError),
-    // DateParserError(String),
-    // TimeParserError(String),
+    DateParserError(String),
+    TimeParserError(String),
+    DurationParserError(String),
+    AmbiguousLocalTime(String),
+    NonexistentLocalTime(String),
+    TimezoneParserError(String),
 }
 
 impl Display for ArgParserError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let (module, e) = match self {
             ArgParserError::ChronoParserError(e) => ("chrono", e.to_string()),
-            // ArgParserError::DateParserError(token) => {
-            //     ("datetime", format!("failed to parse date from '{}'", token))
-            // }
-            // ArgParserError::TimeParserError(token) => {
-            //     ("datetime", format!("failed to parse time from '{}'", token))
-            // }
+            ArgParserError::DateParserError(token) => {
+                ("datetime", format!("failed to parse date from '{}'", token))
+            }
+            ArgParserError::TimeParserError(token) => {
+                ("datetime", format!("failed to parse time from '{}'", token))
+            }
+            ArgParserError::DurationParserError(token) => (
+                "duration",
+                format!("failed to parse duration from '{}'", token),
+            ),
+            ArgParserError::AmbiguousLocalTime(token) => (
+                "datetime",
+                format!("'{}' occurs twice in your local timezone", token),
+            ),
+            ArgParserError::NonexistentLocalTime(token) => (
+                "datetime",
+                format!("'{}' does not exist in your local timezone", token),
+            ),
+            ArgParserError::TimezoneParserError(token) => (
+                "timezone",
+                format!("failed to parse timezone from '{}'", token),
+            ),
         };
         write!(f, "error in {}: {}", module, e)
     }
@@ -26,7 +49,7 @@ impl std::error::Error for ArgParserError {
     fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
         match self {
             ArgParserError::ChronoParserError(e) => Some(e),
-            // _ => None,
+            _ => None,
         }
     }
 }
@@ -36,3 +59,194 @@ impl From<chrono::format::ParseError> for ArgParserError {
         ArgParserError::ChronoParserError(e)
     }
 }
+
+/// An error found by the command line tool itself, holding the message shown
+/// to the user.
+#[derive(Debug)]
+pub enum CliError {
+    NotFound(String),
+    AlreadyExists(String),
+    Conflict(String),
+    Invalid(String),
+    ProblemsFound(String),
+}
+
+impl Display for CliError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let message = match self {
+            CliError::NotFound(message)
+            | CliError::AlreadyExists(message)
+            | CliError::Conflict(message)
+            | CliError::Invalid(message)
+            | CliError::ProblemsFound(message) => message,
+        };
+        write!(f, "{}", message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// The exit code for each kind of error, documented in the README.  Clap
+/// exits with 2 when the command line cannot be parsed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExitStatus {
+    Failure = 1,
+    NotFound = 3,
+    AlreadyExists = 4,
+    Conflict = 5,
+    Invalid = 6,
+    Io = 7,
+}
+
+impl ExitStatus {
+    /// Finds the exit status for the first error in the chain that `dt`
+    /// knows about.
+    pub fn of(e: &anyhow::Error) -> Self {
+        for cause in e.chain() {
+            if let Some(e) = cause.downcast_ref::<CliError>() {
+                return match e {
+                    CliError::NotFound(_) => ExitStatus::NotFound,
+                    CliError::AlreadyExists(_) => ExitStatus::AlreadyExists,
+                    CliError::Conflict(_) => ExitStatus::Conflict,
+                    CliError::Invalid(_) => ExitStatus::Invalid,
+                    CliError::ProblemsFound(_) => ExitStatus::Failure,
+                };
+            }
+            if let Some(e) = cause.downcast_ref::<Error>() {
+                return ExitStatus::of_core(e);
+            }
+            if cause.is::<ArgParserError>() {
+                return ExitStatus::Invalid;
+            }
+            if cause.is::<std::io::Error>() {
+                return ExitStatus::Io;
+            }
+        }
+
+        ExitStatus::Failure
+    }
+
+    fn of_core(e: &Error) -> Self {
+        match e {
+            Error::ProjectNotFound(_)
+            | Error::ActivityTypeNotFound(_)
+            | Error::RepoNotFound(_)
+            | Error::ActivityNotFound(_)
+            | Error::CountNotFound(_)
+            | Error::TagNotFound(_) => ExitStatus::NotFound,
+            Error::ProjectAlreadyExists(_)
+            | Error::ActivityTypeAlreadyExists(_)
+            | Error::RepoAlreadyExists(_)
+            | Error::ActivityAlreadyExists(_)
+            | Error::RunningActivityAlreadyExists(_) => ExitStatus::AlreadyExists,
+            Error::ProjectArchived(_)
+            | Error::ActivityTypeInUse(_)
+            | Error::ActivityTypeArchived(_)
+            | Error::RepoArchived(_)
+            | Error::RunningActivityInOtherProject(_)
+            | Error::ActivityOverlaps(_)
+            | Error::NoLongerOverlaps(_)
+            | Error::ActivityAlreadyPaused(_)
+            | Error::ActivityNotPaused(_)
+            | Error::ActivityStillRunning(_)
+            | Error::HistoryError(_) => ExitStatus::Conflict,
+            Error::ProjectCycle(_)
+            | Error::ActivityEndBeforeStart(_)
+            | Error::ActivitiesInDifferentProjects(_)
+            | Error::ActivitiesOfDifferentTypes(_)
+            | Error::SplitOutsideActivity(_)
+            | Error::NothingToMerge
+            | Error::InvalidTag(_)
+            | Error::InvalidProjectStatus(_)
+            | Error::InvalidBackup(_)
+            | Error::InvalidSetting(_) => ExitStatus::Invalid,
+            Error::Rusqlite(rusqlite::Error::SqliteFailure(e, _))
+                if e.code == rusqlite::ErrorCode::ConstraintViolation =>
+            {
+                ExitStatus::of_constraint(e)
+            }
+            Error::Rusqlite(_)
+            | Error::BackupFailed(_)
+            | Error::DatabaseCorrupt(_)
+            | Error::CorruptRow { .. } => ExitStatus::Io,
+            Error::SerdeJson(_)
+            | Error::ReportError(_)
+            | Error::SchemaTooNew(_)
+            | Error::MigrationError(_) => ExitStatus::Failure,
+        }
+    }
+
+    /// A change the data file refused because it breaks one of the schema's
+    /// constraints: a duplicate key means the item already exists, anything
+    /// else conflicts with the rows already there.
+    fn of_constraint(e: &ffi::Error) -> Self {
+        match e.extended_code {
+            ffi::SQLITE_CONSTRAINT_UNIQUE | ffi::SQLITE_CONSTRAINT_PRIMARYKEY => {
+                ExitStatus::AlreadyExists
+            }
+            _ => ExitStatus::Conflict,
+        }
+    }
+
+    /// The name of the kind of error, used by '--error-format json'.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExitStatus::Failure => "failure",
+            ExitStatus::NotFound => "notfound",
+            ExitStatus::AlreadyExists => "alreadyexists",
+            ExitStatus::Conflict => "conflict",
+            ExitStatus::Invalid => "invalid",
+            ExitStatus::Io => "io",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status_of(sql: &str) -> ExitStatus {
+        let conn = rusqlite::Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+            CREATE TABLE projects (id INTEGER PRIMARY KEY, name TEXT NOT NULL UNIQUE);
+            CREATE TABLE activities (
+                id INTEGER PRIMARY KEY,
+                project INTEGER NOT NULL REFERENCES projects(id)
+            );
+            INSERT INTO projects (id, name) VALUES (1, 'p');",
+        )
+        .unwrap();
+        let e = conn.execute(sql, []).unwrap_err();
+        ExitStatus::of_core(&Error::from(e))
+    }
+
+    #[test]
+    fn duplicate_key_already_exists() {
+        assert_eq!(
+            status_of("INSERT INTO projects (name) VALUES ('p')"),
+            ExitStatus::AlreadyExists
+        );
+        assert_eq!(
+            status_of("INSERT INTO projects (id, name) VALUES (1, 'q')"),
+            ExitStatus::AlreadyExists
+        );
+    }
+
+    #[test]
+    fn other_constraints_conflict() {
+        assert_eq!(
+            status_of("INSERT INTO activities (project) VALUES (2)"),
+            ExitStatus::Conflict
+        );
+        assert_eq!(
+            status_of("INSERT INTO projects (id) VALUES (2)"),
+            ExitStatus::Conflict
+        );
+    }
+
+    #[test]
+    fn other_database_errors_are_io() {
+        assert_eq!(status_of("SELECT * FROM missing"), ExitStatus::Io);
+    }
+}
@@ -1,12 +1,21 @@
 use std::path::PathBuf;
 
-use chrono::{DateTime, Utc};
-use clap::{Args, Parser, Subcommand};
+use chrono::Duration;
+use chrono_tz::Tz;
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use dev_tracker_core::model::ProjectStatus;
+use dev_tracker_core::settings::RunningPolicy;
 
 mod argparser;
-use argparser::*;
+pub use argparser::*;
 
 mod errors;
+pub use errors::*;
+
+mod timezone;
+pub use timezone::*;
 
 #[derive(Parser)]
 #[clap(author, version, about, long_about = None)]
@@ -17,47 +26,142 @@ pub struct Arguments {
     /// doesn't exist.
     #[clap(long, env = "DT_DATA_FILE")]
     pub data_file: Option<PathBuf>,
+    /// The timezone used to read and display dates and times, for example
+    /// 'Europe/London'.  Defaults to the TZ environment variable, then the
+    /// system timezone.
+    #[clap(long, global = true, value_parser = parse_tz)]
+    pub tz: Option<Tz>,
+    /// How to treat a local time that is repeated or skipped when the clocks
+    /// change for daylight saving.
+    #[clap(long, global = true, value_enum, default_value_t)]
+    pub dst: DstPolicy,
+    /// How to report errors.  With 'json' a single JSON object giving the
+    /// kind of error, the exit code, and the message is written to standard
+    /// error.
+    #[clap(long, global = true, value_enum, default_value_t)]
+    pub error_format: ErrorFormat,
+    /// How the list, describe, and status commands write their results. The
+    /// 'json', 'csv', and 'tsv' formats write one record for each item, with
+    /// times in UTC.
+    #[clap(long, global = true, value_enum, default_value_t)]
+    pub output: OutputFormat,
+    /// When to color table output.  With 'auto' colors are used when writing
+    /// to a terminal, unless the NO_COLOR environment variable is set.
+    #[clap(long, global = true, value_enum, default_value_t)]
+    pub color: ColorChoice,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    #[default]
+    Auto,
+    Always,
+    Never,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    Json,
+    Csv,
+    Tsv,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ErrorFormat {
+    #[default]
+    Text,
+    Json,
 }
 
 #[derive(Subcommand)]
 pub enum Command {
-    /// Add a project, activity type, or repository to the tracker.
+    /// Add a project, activity, activity type, or repository to the tracker.
     #[clap(subcommand)]
     Add(AddCommand),
+    /// Archive a project, activity type, or repository. Archived items are
+    /// hidden from listings and cannot be used for new activities or counts,
+    /// but are kept in reports.
+    #[clap(subcommand)]
+    Archive(ArchiveCommand),
     #[clap(subcommand)]
     /// Cancel a running activity.
     Cancel(CancelCommand),
+    /// Check the activities in the tracker for problems.
+    #[clap(subcommand)]
+    Check(CheckCommand),
     /// Counts the lines of code in PROJECT. Returns an error is there is
     /// no such project.
     Count(CountCommandArgs),
     #[clap(subcommand)]
     /// Delete a project, activity, activity type, count, or repository from
-    /// the tracker.
+    /// the tracker. Projects, activity types, and repositories are archived
+    /// unless '--purge' is given.
     Delete(DeleteCommand),
+    /// Back up, restore, check, or compact the database.
+    #[clap(subcommand)]
+    Db(DbCommand),
     /// Describe a project, activity, or count.
     #[clap(subcommand)]
     Describe(DescribeCommand),
+    /// Lists the most recent changes made to the tracker, newest first. Each
+    /// run of 'dt' that changes something is listed with the number of rows
+    /// it changed, use the '-v' flag to list the changes themselves.
+    History(HistoryArgs),
     /// Generate a text or JSON report for a project
     #[clap(subcommand)]
     Generate(GenerateCommand),
-    /// List the projects, activities, activity types, counts, or repositories
-    /// in the tracker.
+    /// List the projects, activities, activity types, counts, repositories, or
+    /// tags in the tracker.
     #[clap(subcommand)]
     List(ListCommand),
+    /// Merge activities.
+    #[clap(subcommand)]
+    Merge(MergeCommand),
+    /// Pause a running activity.
+    #[clap(subcommand)]
+    Pause(PauseCommand),
     /// Rename a project or activity type.
     #[clap(subcommand)]
     Rename(RenameCommand),
+    /// Resume a paused activity, or start a new activity copying the most
+    /// recent finished activity.
+    Resume(ResumeArgs),
+    /// Split an activity in two.
+    #[clap(subcommand)]
+    Split(SplitCommand),
     /// Show the status
     Status(StatusArgs),
-    // #[clap(subcommand)]
-    // Set(),
+    /// Change a setting stored in the data file.
+    #[clap(subcommand)]
+    Set(SetCommand),
     /// Start recording an activity.
     #[clap(subcommand)]
     Start(StartCommand),
     /// Stop recording an activity.
     #[clap(subcommand)]
     Stop(StopCommand),
-    /// Update an activity, activity type, or repository.
+    /// Add tags to an activity or project.
+    #[clap(subcommand)]
+    Tag(TagCommand),
+    /// Reverts the last COUNT runs of 'dt' that changed something, newest
+    /// first, which have not already been undone. Either all of them are
+    /// reverted or none are. See 'dt history' for what will be undone.
+    Undo(UndoArgs),
+    /// Restore an archived project, activity type, or repository.
+    #[clap(subcommand)]
+    Unarchive(UnarchiveCommand),
+    /// Remove tags from an activity or project.
+    #[clap(subcommand)]
+    Untag(UntagCommand),
+    /// Stops any running activities, in any project, and starts recording a
+    /// new activity for PROJECT with an activity type of ACTIVITY_TYPE and an
+    /// optional DESCRIPTION at the same instant. No counts are made for the
+    /// stopped activities. Returns an error if there is no such project or
+    /// activity type, in which case nothing is stopped.
+    Switch(SwitchArgs),
+    /// Update a project, activity, activity type, or repository.
     #[clap(subcommand)]
     Update(UpdateCommand),
 }
@@ -66,9 +170,16 @@ pub enum Command {
 pub enum AddCommand {
     /// Creates a new project with NAME and optionally creates a repository for
     /// the project at PATH. Repositories can also be added to a project using
-    /// the 'dt add repo <PROJECT> <PATH>' command. Returns an error if there
-    /// is an existing project with the same name.
+    /// the 'dt add repo <PROJECT> <PATH>' command. Use '--parent' to make it
+    /// a sub-project of another project. Returns an error if there is an
+    /// existing project with the same name or no such parent project.
     Project(AddProjectArgs),
+    /// Records a finished activity for PROJECT with an activity type of
+    /// ACTIVITY_TYPE and an optional DESCRIPTION that ran from START until
+    /// END. Use this to record work done earlier. Returns an error if there is
+    /// no such project or activity type, if END is before START, or if the
+    /// activity would overlap an existing activity for the project.
+    Activity(AddActivityArgs),
     /// Creates a new activity type with NAME and and optional DESCRPTION.
     /// Returns an error if there is an existing activity type with the same
     /// name.
@@ -86,6 +197,30 @@ pub struct AddProjectArgs {
     pub name: String,
     /// An optional path for the projects source code, must be unique.
     pub path: Option<PathBuf>,
+    /// Make the project a sub-project of PARENT.
+    #[arg(long)]
+    pub parent: Option<String>,
+}
+
+#[derive(Args)]
+pub struct AddActivityArgs {
+    /// The project on which to record the activity.
+    pub project: String,
+    /// The type of the activity.
+    pub activity_type: String,
+    /// The start date and time in your local timezone, for example
+    /// YYYY-MM-DDTHH:MM, 'yesterday 14:30', or '2h ago'.
+    #[arg(long, value_parser = parse_datetime)]
+    pub start: TimeArg,
+    /// The end date and time in your local timezone, for example
+    /// YYYY-MM-DDTHH:MM, 'yesterday 16:00', or '30m ago'.
+    #[arg(long, value_parser = parse_datetime)]
+    pub end: TimeArg,
+    /// An optional description of the activity.
+    pub description: Option<String>,
+    /// Tag the activity, can be given more than once.
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
 }
 
 #[derive(Args)]
@@ -111,6 +246,53 @@ pub struct CountCommandArgs {
     pub project: String,
 }
 
+#[derive(Subcommand)]
+pub enum ArchiveCommand {
+    /// Archives the project with NAME. Returns an error if there is no such
+    /// project.
+    Project(ArchiveProjectArgs),
+    /// Archives the activity type with NAME. Returns an error if there is no
+    /// such activity type or if it is the 'Unknown' activity type.
+    #[command(alias = "at")]
+    ActivityType(ArchiveActivityTypeArgs),
+    /// Archives the repository with PATH, it will no longer be counted.
+    /// Returns an error if there is no such repository.
+    Repo(ArchiveRepoArgs),
+}
+
+#[derive(Subcommand)]
+pub enum UnarchiveCommand {
+    /// Restores the archived project with NAME to the status it had before it
+    /// was archived, or to active if that is not known. Returns an error if
+    /// there is no such project.
+    Project(ArchiveProjectArgs),
+    /// Restores the archived activity type with NAME. Returns an error if
+    /// there is no such activity type.
+    #[command(alias = "at")]
+    ActivityType(ArchiveActivityTypeArgs),
+    /// Restores the archived repository with PATH. Returns an error if there
+    /// is no such repository.
+    Repo(ArchiveRepoArgs),
+}
+
+#[derive(Args)]
+pub struct ArchiveProjectArgs {
+    /// The project to archive or restore.
+    pub name: String,
+}
+
+#[derive(Args)]
+pub struct ArchiveActivityTypeArgs {
+    /// The activity type to archive or restore.
+    pub name: String,
+}
+
+#[derive(Args)]
+pub struct ArchiveRepoArgs {
+    /// The repository to archive or restore.
+    pub path: PathBuf,
+}
+
 #[derive(Subcommand)]
 pub enum CancelCommand {
     /// Cancels the running activity for PROJECT. Returns an error if there is
@@ -124,26 +306,59 @@ pub struct CancelActivityTypeArgs {
     pub project: String,
 }
 
+#[derive(Subcommand)]
+pub enum CheckCommand {
+    /// Lists the pairs of activities whose times overlap, in any project or
+    /// only those involving PROJECT. Use '--fix' to remove the overlaps.
+    /// Returns an error if there is no such project.
+    Overlaps(CheckOverlapsArgs),
+}
+
+#[derive(Args)]
+pub struct CheckOverlapsArgs {
+    /// Only check activities overlapping those of this project.
+    pub project: Option<String>,
+    /// Remove the overlaps. With 'trim' the earlier activity ends when the
+    /// later one starts. With 'merge' both activities, with their tags and
+    /// descriptions, are combined into the earlier one, activities in
+    /// different projects or with different activity types are skipped, as
+    /// are those where the merged activity would overlap another activity.
+    /// With 'split' the later activity is cut out of the earlier one, and any
+    /// part of the earlier activity after the later one becomes a new
+    /// activity with the same tags.
+    #[arg(long, value_enum)]
+    pub fix: Option<OverlapFix>,
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum OverlapFix {
+    Trim,
+    Merge,
+    Split,
+}
+
 #[derive(Subcommand)]
 pub enum DeleteCommand {
-    /// Deletes the project with NAME and all of it's associated activities,
-    /// repositories, and counts. Returns an error if there is no such project.
+    /// Archives the project with NAME, or with '--purge' permanently deletes
+    /// it and all of it's associated activities, repositories, and counts.
+    /// Returns an error if there is no such project.
     Project(DeleteProjectArgs),
     /// Deletes the activity with ID. Returns an error if there is no such
-    /// activity. To obtain the ID of an activity use the '-v' flag for the 'dt
-    /// list activities <PROJECT>' command. Returns an error if there is no such
-    /// activity.
+    /// activity. The ID of an activity is shown by the 'dt list activities
+    /// <PROJECT>' command. Returns an error if there is no such activity.
     Activity(DeleteActivityArgs),
-    /// Deletes the activity type with NAME. Returns an error if there is no
-    /// such activity type.
+    /// Archives the activity type with NAME, or with '--purge' permanently
+    /// deletes it and changes the activity type of it's activities to
+    /// 'Unknown'. Returns an error if there is no such activity type.
     #[command(alias = "at")]
     ActivityType(DeleteActivityTypeArgs),
     /// Deletes the count with ID. To obtain the ID of a count use the '-v' flag
     /// for the 'dt list counts <PROJECT>' command. Returns an error if there is
     /// no such count.
     Count(DeleteCountArgs),
-    /// Deletes the repository with PATH and all of it's associated counts.
-    /// Returns an error if there is no such repository.
+    /// Archives the repository with PATH, or with '--purge' permanently
+    /// deletes it and all of it's associated counts. Returns an error if there
+    /// is no such repository.
     Repo(DeleteRepoArgs),
 }
 
@@ -151,6 +366,9 @@ pub enum DeleteCommand {
 pub struct DeleteProjectArgs {
     /// The project to delete.
     pub name: String,
+    /// Permanently delete rather than archive.
+    #[arg(long)]
+    pub purge: bool,
 }
 
 #[derive(Args)]
@@ -161,8 +379,11 @@ pub struct DeleteActivityArgs {
 
 #[derive(Args)]
 pub struct DeleteActivityTypeArgs {
-    /// The activity type to delete/
+    /// The activity type to delete.
     pub name: String,
+    /// Permanently delete rather than archive.
+    #[arg(long)]
+    pub purge: bool,
 }
 
 #[derive(Args)]
@@ -176,6 +397,43 @@ pub struct DeleteRepoArgs {
     /// The repository to delete.  Only deletes from the tracker, does not
     /// delete your files!
     pub path: PathBuf,
+    /// Permanently delete rather than archive.
+    #[arg(long)]
+    pub purge: bool,
+}
+
+#[derive(Subcommand)]
+pub enum DbCommand {
+    /// Copies the database to FILE while it is in use, replacing anything
+    /// already in FILE.
+    Backup(DbFileArgs),
+    /// Replaces the database with the backup in FILE, migrating it if it was
+    /// made by an older version of 'dt'. An automatic backup of the current
+    /// database is made first. Returns an error if FILE is not a dev-tracker
+    /// database or was made by a newer version of 'dt'.
+    Restore(DbFileArgs),
+    /// Rebuilds the database file to reclaim the space left by deleted rows.
+    Vacuum,
+    /// Checks the database for damage and for problems with its rows, such
+    /// as activities, repositories, or counts whose project or repository is
+    /// missing, projects with more than one running activity, activities that
+    /// end before they start, duplicate names or paths, and counts that
+    /// cannot be read. Use '--fix' to repair the problems, an automatic
+    /// backup is made first. Exits with an error if any problems remain.
+    Check(DbCheckArgs),
+}
+
+#[derive(Args)]
+pub struct DbCheckArgs {
+    /// Repair the problems that are found.
+    #[arg(long)]
+    pub fix: bool,
+}
+
+#[derive(Args)]
+pub struct DbFileArgs {
+    /// The backup file.
+    pub file: PathBuf,
 }
 
 #[derive(Subcommand)]
@@ -186,9 +444,8 @@ pub enum DescribeCommand {
     Project(DescribeProjectArgs),
     /// Describes the activity with ID. Lists the project name, the activity
     /// type, the start and end times (or still running), and the duration in
-    /// minutes. To obtain the ID of an activity use the '-v' flag for the 'dt
-    /// list activities <PROJECT>' command. Returns an error if there is no
-    /// such activity.
+    /// minutes. The ID of an activity is shown by the 'dt list activities
+    /// <PROJECT>' command. Returns an error if there is no such activity.
     Activity(DescribeActivityArgs),
     /// Describes the count with ID. Lists project name, the repository path,
     /// the date and time of the count, and the lines of code. To obtain
@@ -229,37 +486,70 @@ pub struct GenerateArgs {
     /// The name of the project to report on.  Use 'all' to report on all
     /// projects.  
     pub name: String,
-    /// An optional start date for the report.  The date format is DD-MM-YYYY.
-    /// If omitted the report uses all activites up until the end date or now.
-    #[arg(value_parser = parse_date)]
-    pub start: Option<DateTime<Utc>>,
-    /// An optional end date for the report.  The date format is DD-MM-YYYY.
-    /// If omitted the report uses an end date of now.
-    #[arg(value_parser = parse_date)]
-    pub end: Option<DateTime<Utc>>,
+    /// An optional start date for the report, for example DD-MM-YYYY,
+    /// YYYY-MM-DD, 'monday', or 'last week'.  The report starts at the
+    /// beginning of the day or period.  If omitted the report uses all
+    /// activites up until the end date or now.
+    #[arg(value_parser = parse_datetime)]
+    pub start: Option<TimeArg>,
+    /// An optional end date for the report, in the same formats as the start
+    /// date.  The report includes the whole of the day or period.  If omitted
+    /// the report uses an end date of now.
+    #[arg(value_parser = parse_datetime)]
+    pub end: Option<TimeArg>,
+    /// Report on a named period instead of giving start and end dates, one
+    /// of today, yesterday, this-week, last-week, this-month, last-month,
+    /// this-year, or last-year.
+    #[arg(long, value_parser = parse_range, conflicts_with_all = ["start", "end"])]
+    pub period: Option<TimeArg>,
+    /// Only report on activities with this tag, either directly or through
+    /// the project.
+    #[arg(long)]
+    pub tag: Option<String>,
+}
+
+#[derive(Args)]
+pub struct HistoryArgs {
+    /// List the changed rows.
+    #[clap(short, action)]
+    pub verbose: bool,
+    /// The number of runs to list.
+    #[arg(short = 'n', long, default_value_t = 10)]
+    pub count: u64,
 }
 
 #[derive(Subcommand)]
 pub enum ListCommand {
-    /// List all of the projects in the database. Use the optional '-v' flag
-    /// to list the ID numbers for the projects.
+    /// List all of the projects in the database, with sub-projects indented
+    /// below their parent, except archived projects unless the '-a' flag is
+    /// given. Use the optional '-v' flag to list the
+    /// ID numbers for the projects.
     Projects(ListProjectArgs),
-    /// List all of the activities for PROJECT. Use the optional '-v' flag to
-    /// list the ID numbers for the activities. Returns an error if there is
-    /// no such project.
+    /// List the activities for PROJECT, or for every project with
+    /// '--all-projects', as a table with a total at the foot. Use the options
+    /// to choose which activities are listed and in what order. Long names
+    /// and descriptions are cut short to fit the terminal unless the '-v'
+    /// flag is given. Returns an error if there is no such project or
+    /// activity type.
     Activities(ListActivityArgs),
-    /// List all of the activity types. Use the optional '-v' flag to list the
-    /// ID numbers for the activity types.
+    /// List all of the activity types, except archived activity types unless
+    /// the '-a' flag is given. Use the optional '-v' flag to list the ID
+    /// numbers for the activity types.
     #[command(alias = "ats")]
     ActivityTypes(ListActivityTypeArgs),
-    /// List all of the counts for PROJECT. Use the optional '-v' flag to list
-    /// the ID numbers for the counts. Returns an error if there is no such
-    /// project.
+    /// List all of the counts for PROJECT, or only those for one of its
+    /// repositories with '--repo'. Use the optional '-v' flag to list the ID
+    /// numbers for the counts. Returns an error if there is no such project,
+    /// or no such repository for the project.
     Counts(ListCountArgs),
-    /// List all of the repositories for PROJECT. Use the optional '-v' flag to
-    /// list the ID numbers for the repositories. Returns an error if there
+    /// List all of the repositories for PROJECT, except archived repositories
+    /// unless the '-a' flag is given. Use the optional '-v' flag to list the
+    /// ID numbers for the repositories. Returns an error if there
     /// is no such project.
     Repos(ListRepoArgs),
+    /// List all of the tags. Use the optional '-v' flag to list the ID
+    /// numbers for the tags.
+    Tags(ListTagArgs),
 }
 
 #[derive(Args)]
@@ -267,15 +557,62 @@ pub struct ListProjectArgs {
     /// List the ID numbers.
     #[clap(short, action)]
     pub verbose: bool,
+    /// Include archived projects.
+    #[clap(short, long, action)]
+    pub all: bool,
 }
 
 #[derive(Args)]
 pub struct ListActivityArgs {
-    /// List the ID numbers.
+    /// Show names, descriptions, and tags in full.
     #[clap(short, action)]
     pub verbose: bool,
     /// The project with activities to list.
-    pub project: String,
+    #[arg(required_unless_present = "all_projects")]
+    pub project: Option<String>,
+    /// List the activities for every project.
+    #[arg(long, conflicts_with = "project")]
+    pub all_projects: bool,
+    /// Only list activities that started on or after this date and time, for
+    /// example YYYY-MM-DD, 'monday', or '2h ago'.
+    #[arg(long, value_parser = parse_datetime)]
+    pub since: Option<TimeArg>,
+    /// Only list activities that started on or before this date and time,
+    /// in the same formats as '--since'.  A day or period includes the whole
+    /// of it.
+    #[arg(long, value_parser = parse_datetime)]
+    pub until: Option<TimeArg>,
+    /// Only list activities of this activity type.
+    #[arg(long = "type")]
+    pub activity_type: Option<String>,
+    /// Only list activities with this tag, either directly or through the
+    /// project.
+    #[arg(long)]
+    pub tag: Option<String>,
+    /// Only list activities that are still running.
+    #[arg(long)]
+    pub running: bool,
+    /// The order to list activities in.
+    #[arg(long, value_enum, default_value_t)]
+    pub sort: ActivitySort,
+    /// List activities in the opposite order, for example the most recent
+    /// first.
+    #[arg(long)]
+    pub reverse: bool,
+    /// List at most this many activities.
+    #[arg(long)]
+    pub limit: Option<u64>,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ActivitySort {
+    /// By start time.
+    #[default]
+    Start,
+    /// By time worked.
+    Duration,
+    /// By activity type name.
+    Type,
 }
 
 #[derive(Args)]
@@ -283,6 +620,9 @@ pub struct ListActivityTypeArgs {
     /// List the ID numbers.
     #[clap(short, action)]
     pub verbose: bool,
+    /// Include archived activity types.
+    #[clap(short, long, action)]
+    pub all: bool,
 }
 
 #[derive(Args)]
@@ -292,6 +632,9 @@ pub struct ListCountArgs {
     pub verbose: bool,
     /// The project with counts to list.
     pub project: String,
+    /// Only list the counts for this repository.
+    #[arg(long)]
+    pub repo: Option<PathBuf>,
 }
 
 #[derive(Args)]
@@ -299,10 +642,54 @@ pub struct ListRepoArgs {
     /// List the ID numbers.
     #[clap(short, action)]
     pub verbose: bool,
+    /// Include archived repositories.
+    #[clap(short, long, action)]
+    pub all: bool,
     /// The project with repositories to list.
     pub project: String,
 }
 
+#[derive(Subcommand)]
+pub enum MergeCommand {
+    /// Merges the activities with IDS into the earliest of them, which keeps
+    /// its ID and activity type and gets the descriptions and tags of all of
+    /// them. Gaps between the activities are recorded as pauses so the total
+    /// time is unchanged. The ID of an activity is shown by the 'dt list activities
+    /// <PROJECT>' command. Returns an error if there is no such activity, if
+    /// the activities belong to different projects, if another activity falls
+    /// between them, or if any but the last activity is still running.
+    Activities(MergeActivitiesArgs),
+}
+
+#[derive(Args)]
+pub struct MergeActivitiesArgs {
+    /// The activities to merge, at least two.
+    #[arg(num_args = 2.., required = true)]
+    pub ids: Vec<u64>,
+}
+
+#[derive(Subcommand)]
+pub enum PauseCommand {
+    /// Pauses the running activity for PROJECT, for example while taking a
+    /// break. Time spent paused is not counted in the duration of the
+    /// activity. Returns an error if there is no such project, no running
+    /// activity on the project, or if the activity is already paused.
+    Activity(PauseActivityArgs),
+}
+
+#[derive(Args)]
+pub struct PauseActivityArgs {
+    /// The project which has a running activity to pause.
+    pub project: String,
+}
+
+#[derive(Args)]
+pub struct ListTagArgs {
+    /// List the ID numbers.
+    #[clap(short, action)]
+    pub verbose: bool,
+}
+
 #[derive(Subcommand)]
 pub enum RenameCommand {
     /// Renames a project from OLD_NAME to NEW_NAME. Returns an error if there
@@ -332,6 +719,78 @@ pub struct RenameActivityTypeArgs {
     pub new_name: String,
 }
 
+#[derive(Args)]
+#[command(args_conflicts_with_subcommands = true)]
+pub struct ResumeArgs {
+    #[clap(subcommand)]
+    pub command: Option<ResumeCommand>,
+    /// Start a new activity with the same project, activity type, and
+    /// description as the most recent finished activity in PROJECT, or in any
+    /// project if PROJECT is omitted. Returns an error if there is no such
+    /// project, no finished activity to copy, or if an activity is already
+    /// running for the project.
+    pub project: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum ResumeCommand {
+    /// Resumes the paused activity for PROJECT. Returns an error if there is
+    /// no such project, no running activity on the project, or if the activity
+    /// is not paused.
+    Activity(ResumeActivityArgs),
+}
+
+#[derive(Args)]
+pub struct ResumeActivityArgs {
+    /// The project which has a paused activity to resume.
+    pub project: String,
+}
+
+#[derive(Subcommand)]
+pub enum SetCommand {
+    /// Sets whether activities may run in more than one project at the same
+    /// time. With 'per-project' each project may have its own running
+    /// activity, with 'refuse' starting an activity fails while another
+    /// project has one running, and with 'auto-stop' starting an activity
+    /// stops the running activities in other projects.
+    RunningPolicy(SetRunningPolicyArgs),
+}
+
+#[derive(Args)]
+pub struct SetRunningPolicyArgs {
+    /// The new running activity policy.
+    #[arg(value_parser = PossibleValuesParser::new(["per-project", "refuse", "auto-stop"])
+        .map(|s| s.parse::<RunningPolicy>().expect("should only get possible values")))]
+    pub policy: RunningPolicy,
+}
+
+#[derive(Subcommand)]
+pub enum SplitCommand {
+    /// Splits the activity with ID in two at TIME. The activity keeps its ID
+    /// and the part before TIME, the part from TIME onwards becomes a new
+    /// activity with the same activity type and description unless they are
+    /// given, and the same tags. The ID of an activity is shown by the 'dt list activities
+    /// <PROJECT>' command. Returns an error if there is no such activity or
+    /// activity type, or if TIME is not within the activity.
+    Activity(SplitActivityArgs),
+}
+
+#[derive(Args)]
+pub struct SplitActivityArgs {
+    /// The activity to split.
+    pub id: u64,
+    /// The date and time in your local timezone at which to split the
+    /// activity, for example YYYY-MM-DDTHH:MM or '14:30'.
+    #[arg(value_parser = parse_datetime)]
+    pub time: TimeArg,
+    /// The activity type for the new activity.
+    #[arg(long)]
+    pub activity_type: Option<String>,
+    /// The description for the new activity.
+    #[arg(long)]
+    pub description: Option<String>,
+}
+
 #[derive(Args)]
 pub struct StatusArgs {
     /// The name of the project
@@ -342,8 +801,9 @@ pub struct StatusArgs {
 pub enum StartCommand {
     /// Start recording an activity for PROJECT with an activity type of
     /// ACTIVITY_TYPE and an optional DESCRIPTION. Returns an error if
-    /// there is no such project or activity type, or if there already an
-    /// activity in progress for the project.
+    /// there is no such project or activity type, if there already an
+    /// activity in progress for the project, or if the activity would overlap
+    /// an existing activity for the project.
     Activity(StartActivityArgs),
 }
 
@@ -355,12 +815,24 @@ pub struct StartActivityArgs {
     pub activity_type: String,
     /// An optional description of the activity.
     pub description: Option<String>,
+    /// Start the activity at this date and time in your local timezone rather
+    /// than now, for example YYYY-MM-DDTHH:MM or '09:15'.
+    #[arg(long, value_parser = parse_datetime, conflicts_with = "ago")]
+    pub at: Option<TimeArg>,
+    /// Start the activity this long ago rather than now, for example '15m',
+    /// '2h', or '1h30m'.
+    #[arg(long, value_parser = parse_duration)]
+    pub ago: Option<Duration>,
+    /// Tag the activity, can be given more than once.
+    #[arg(long = "tag")]
+    pub tags: Vec<String>,
 }
 
 #[derive(Subcommand)]
 pub enum StopCommand {
     /// Stops the current activity for PROJECT. Returns an error if there is
-    /// no such project or of there is no current activity in progress.
+    /// no such project, if there is no current activity in progress, or if the
+    /// stop time is before the activity start time.
     Activity(StopActivityArgs),
 }
 
@@ -375,10 +847,80 @@ pub struct StopActivityArgs {
     /// in a project at the end of the activity.
     #[clap(long, action)]
     pub no_count: bool,
+    /// Stop the activity at this date and time in your local timezone rather
+    /// than now, for example YYYY-MM-DDTHH:MM or '17:30'.
+    #[arg(long, value_parser = parse_datetime, conflicts_with = "ago")]
+    pub at: Option<TimeArg>,
+    /// Stop the activity this long ago rather than now, for example '15m',
+    /// '2h', or '1h30m'.
+    #[arg(long, value_parser = parse_duration)]
+    pub ago: Option<Duration>,
+}
+
+#[derive(Args)]
+pub struct SwitchArgs {
+    /// The project on which to start recording an activity.
+    pub project: String,
+    /// The type of the activity.
+    pub activity_type: String,
+    /// An optional description of the activity.
+    pub description: Option<String>,
+}
+
+#[derive(Subcommand)]
+pub enum TagCommand {
+    /// Adds TAGS to the activity with ID, tags are created the first time they
+    /// are used. The ID of an activity is shown by the 'dt list activities
+    /// <PROJECT>' command. Returns an error if there is no such activity or if
+    /// a tag is empty or contains spaces or commas, in which case no tags are
+    /// added.
+    Activity(TagActivityArgs),
+    /// Adds TAGS to the project with NAME, the tags apply to all of the
+    /// activities for the project. Returns an error if there is no such
+    /// project or if a tag is empty or contains spaces or commas, in which case
+    /// no tags are added.
+    Project(TagProjectArgs),
+}
+
+#[derive(Subcommand)]
+pub enum UntagCommand {
+    /// Removes TAGS from the activity with ID. Returns an error if there is no
+    /// such activity or no such tag, in which case no tags are removed.
+    Activity(TagActivityArgs),
+    /// Removes TAGS from the project with NAME. Returns an error if there is
+    /// no such project or no such tag, in which case no tags are removed.
+    Project(TagProjectArgs),
+}
+
+#[derive(Args)]
+pub struct TagActivityArgs {
+    /// The activity to tag.
+    pub id: u64,
+    /// The tags.
+    #[arg(required = true)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct TagProjectArgs {
+    /// The project to tag.
+    pub name: String,
+    /// The tags.
+    #[arg(required = true)]
+    pub tags: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct UndoArgs {
+    /// The number of runs to undo.
+    #[arg(default_value_t = 1)]
+    pub count: u64,
 }
 
 #[derive(Subcommand)]
 pub enum UpdateCommand {
+    /// Update the details of a project.
+    Project(UpdateProjectArgs),
     /// Update the values associated with an activity.
     Activity(UpdateActivityArgs),
     /// Update the description of an actovoty type.
@@ -388,6 +930,95 @@ pub enum UpdateCommand {
     Repo(UpdateRepoArgs),
 }
 
+#[derive(Args)]
+pub struct UpdateProjectArgs {
+    #[clap(subcommand)]
+    pub command: UpdateProjectCommand,
+}
+
+#[derive(Subcommand)]
+pub enum UpdateProjectCommand {
+    /// Updates the description for the project with NAME. Use this command
+    /// with no value for DESCRIPTION to remove an existing description.
+    /// Returns an error if there is no such project.
+    Description(UpdateProjectDescriptionArgs),
+    /// Updates the status for the project with NAME, one of 'active',
+    /// 'paused', 'finished', or 'archived'. Archived projects are hidden by
+    /// 'dt list projects'. Returns an error if there is no such project.
+    Status(UpdateProjectStatusArgs),
+    /// Updates the date the project with NAME was created. CREATED is in your
+    /// local timezone, for example YYYY-MM-DD or 'yesterday'. Returns an
+    /// error if there is no such project.
+    Created(UpdateProjectCreatedArgs),
+    /// Updates the date by which the project with NAME should be finished.
+    /// TARGET is in your local timezone, for example YYYY-MM-DD or
+    /// 'next-month'. Use this command with no value for TARGET to remove an
+    /// existing target date. Returns an error if there is no such project.
+    Target(UpdateProjectTargetArgs),
+    /// Updates the homepage or issue tracker URL for the project with NAME.
+    /// Use this command with no value for URL to remove an existing URL.
+    /// Returns an error if there is no such project.
+    Url(UpdateProjectUrlArgs),
+    /// Makes the project with NAME a sub-project of PARENT. Use this command
+    /// with no value for PARENT to make it a top level project. Returns an
+    /// error if there is no such project, or if PARENT is the project itself
+    /// or one of its sub-projects.
+    Parent(UpdateProjectParentArgs),
+}
+
+#[derive(Args)]
+pub struct UpdateProjectDescriptionArgs {
+    /// The project to update.
+    pub name: String,
+    /// The new description, leave blank to remove an existing description.
+    pub description: Option<String>,
+}
+
+#[derive(Args)]
+pub struct UpdateProjectStatusArgs {
+    /// The project to update.
+    pub name: String,
+    /// The new status.
+    #[arg(value_parser = PossibleValuesParser::new(["active", "paused", "finished", "archived"])
+        .map(|s| s.parse::<ProjectStatus>().expect("should only get possible values")))]
+    pub status: ProjectStatus,
+}
+
+#[derive(Args)]
+pub struct UpdateProjectCreatedArgs {
+    /// The project to update.
+    pub name: String,
+    /// The new created date in your local timezone.
+    #[arg(value_parser = parse_datetime)]
+    pub created: TimeArg,
+}
+
+#[derive(Args)]
+pub struct UpdateProjectTargetArgs {
+    /// The project to update.
+    pub name: String,
+    /// The new target date in your local timezone, leave blank to remove an
+    /// existing target date.
+    #[arg(value_parser = parse_datetime)]
+    pub target: Option<TimeArg>,
+}
+
+#[derive(Args)]
+pub struct UpdateProjectUrlArgs {
+    /// The project to update.
+    pub name: String,
+    /// The new URL, leave blank to remove an existing URL.
+    pub url: Option<String>,
+}
+
+#[derive(Args)]
+pub struct UpdateProjectParentArgs {
+    /// The project to update.
+    pub name: String,
+    /// The new parent project, leave blank to make a top level project.
+    pub parent: Option<String>,
+}
+
 #[derive(Args)]
 pub struct UpdateActivityArgs {
     #[clap(subcommand)]
@@ -396,28 +1027,32 @@ pub struct UpdateActivityArgs {
 
 #[derive(Subcommand)]
 pub enum UpdateActivityCommand {
-    /// Updates the end time for the activity with ID.  END should be in the
-    /// format YYYY-MM-DDTHH:MM where the date and time are in your local
-    /// timezone and the time uses the 24-hour clock. To obtain the ID of an
-    /// activity use the '-v' flag for the 'dt list activities <PROJECT>'
-    /// command. Returns an error if there is no such activity, if the new
-    /// end time is before the activity start time, or if there is an error
-    /// parsing the date and time from your local timezone into UTC.
+    /// Updates the start time for the activity with ID.  START is in your local
+    /// timezone, for example YYYY-MM-DDTHH:MM, 'yesterday 14:30', or '2h ago'.
+    /// The ID of an activity is shown by the 'dt list activities <PROJECT>'
+    /// command. Returns an error if there is no such activity, if the new start
+    /// time is after the activity end time, or if the activity would overlap an
+    /// existing activity for the project.
+    Start(UpdateActivityStartArgs),
+    /// Updates the end time for the activity with ID.  END is in your local
+    /// timezone, for example YYYY-MM-DDTHH:MM, 'yesterday 17:00', or '2h ago'.
+    /// The ID of an activity is shown by the 'dt list activities <PROJECT>'
+    /// command. Returns an error if there is no such activity, if the new end
+    /// time is before the activity start time, or if there is an error parsing
+    /// the date and time from your local timezone into UTC.
     End(UpdateActivityEndArgs),
     /// Updates the activity type for the activity with ID. Returns an error if
     /// there is no such activity or no such activity type.
     #[command(alias = "at")]
     ActivityType(UpdateActivityActivityTypeArgs),
-    /// Updates the description for the activity with ID. Use this command
-    /// with no value for DESCRIPTION to remove an existing description. To
-    /// obtain the ID of an activity use the '-v' flag for the 'dt list
-    /// activities <PROJECT>' command. Returns an error if there is no such
-    /// activity.
+    /// Updates the description for the activity with ID. Use this command with
+    /// no value for DESCRIPTION to remove an existing description. The ID of an
+    /// activity is shown by the 'dt list activities <PROJECT>' command. Returns
+    /// an error if there is no such activity.
     Description(UpdateActivityDescriptionArgs),
-    /// Updates the project for the activity with ID. To obtain the ID of an
-    /// activity use the '-v' flag for the 'dt list activities <PROJECT>'
-    /// command. Returns an error if there is no such activity or no such
-    /// project.
+    /// Updates the project for the activity with ID. The ID of an activity is
+    /// shown by the 'dt list activities <PROJECT>' command. Returns an error if
+    /// there is no such activity or no such project.
     Project(UpdateActivityProjectArgs),
 }
 
@@ -428,15 +1063,22 @@ pub struct UpdateActivityDescriptionArgs {
     /// The new description, leave blank to remove an existing description.
     pub description: Option<String>,
 }
+#[derive(Args)]
+pub struct UpdateActivityStartArgs {
+    /// The activity to update.
+    pub id: u64,
+    /// The new start date and time in your local timezone.
+    #[arg(value_parser = parse_datetime)]
+    pub start: TimeArg,
+}
+
 #[derive(Args)]
 pub struct UpdateActivityEndArgs {
     /// The activity to update.
     pub id: u64,
-    /// The new end date and time.  Must be in the
-    /// format YYYY-MM-DDTHH:MM where the date and time are in your local
-    /// timezone and the time uses the 24-hour clock.
+    /// The new end date and time in your local timezone.
     #[arg(value_parser = parse_datetime)]
-    pub end: DateTime<Utc>,
+    pub end: TimeArg,
 }
 
 #[derive(Args)]
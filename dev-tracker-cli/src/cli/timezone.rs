@@ -0,0 +1,189 @@
+use chrono::{DateTime, Duration, LocalResult, NaiveDate, NaiveDateTime, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+
+use super::errors::ArgParserError;
+
+/// How to resolve a local time that falls in a daylight saving transition.
+#[derive(Clone, Copy, Debug, Default, ValueEnum)]
+pub enum DstPolicy {
+    /// Return an error and ask for an unambiguous time.
+    #[default]
+    Reject,
+    /// Use the first occurrence of a repeated time.
+    Earliest,
+    /// Use the second occurrence of a repeated time.
+    Latest,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct TimeContext {
+    tz: Tz,
+    policy: DstPolicy,
+}
+
+impl TimeContext {
+    /// Uses `tz` if given, otherwise the zone named by the TZ environment
+    /// variable, otherwise the system timezone, falling back to UTC.
+    pub fn new(tz: Option<Tz>, policy: DstPolicy) -> Self {
+        let tz = tz
+            .or_else(|| {
+                let name = std::env::var("TZ").ok()?;
+                name.trim_start_matches(':').parse().ok()
+            })
+            .or_else(|| iana_time_zone::get_timezone().ok()?.parse().ok())
+            .unwrap_or(Tz::UTC);
+
+        Self { tz, policy }
+    }
+
+    pub fn now(&self) -> DateTime<Tz> {
+        Utc::now().with_timezone(&self.tz)
+    }
+
+    pub fn tz(&self) -> &Tz {
+        &self.tz
+    }
+
+    pub fn local(&self, datetime: DateTime<Utc>) -> DateTime<Tz> {
+        datetime.with_timezone(&self.tz)
+    }
+
+    /// Converts a local time to UTC, following the daylight saving policy for
+    /// times that are repeated or skipped when the clocks change.
+    pub fn resolve(&self, datetime: NaiveDateTime) -> Result<DateTime<Utc>, ArgParserError> {
+        let token = || datetime.format("%Y-%m-%d %H:%M").to_string();
+        match (self.tz.from_local_datetime(&datetime), self.policy) {
+            (LocalResult::Single(datetime), _) => Ok(datetime.with_timezone(&Utc)),
+            (LocalResult::Ambiguous(_, _), DstPolicy::Reject) => {
+                Err(ArgParserError::AmbiguousLocalTime(token()))
+            }
+            (LocalResult::Ambiguous(earliest, _), DstPolicy::Earliest) => {
+                Ok(earliest.with_timezone(&Utc))
+            }
+            (LocalResult::Ambiguous(_, latest), DstPolicy::Latest) => {
+                Ok(latest.with_timezone(&Utc))
+            }
+            (LocalResult::None, DstPolicy::Reject) => {
+                Err(ArgParserError::NonexistentLocalTime(token()))
+            }
+            (LocalResult::None, _) => self
+                .after_gap(datetime)
+                .ok_or_else(|| ArgParserError::NonexistentLocalTime(token())),
+        }
+    }
+
+    /// The start of `date`, which is never rejected: a repeated midnight uses
+    /// the first occurrence and a skipped midnight uses the moment the clocks
+    /// change.
+    pub fn start_of_day(&self, date: NaiveDate) -> DateTime<Utc> {
+        let midnight = date
+            .and_hms_opt(0, 0, 0)
+            .expect("should always be able to create a time of midnight");
+        match self.tz.from_local_datetime(&midnight) {
+            LocalResult::Single(datetime) | LocalResult::Ambiguous(datetime, _) => {
+                datetime.with_timezone(&Utc)
+            }
+            LocalResult::None => self
+                .after_gap(midnight)
+                .expect("a timezone transition should never skip more than a day"),
+        }
+    }
+
+    // A local time skipped when the clocks go forward is moved to the moment
+    // the clocks change, clocks always change on a whole minute.
+    fn after_gap(&self, datetime: NaiveDateTime) -> Option<DateTime<Utc>> {
+        let mut probe = datetime.with_second(0)?.with_nanosecond(0)?;
+        for _ in 0..(48 * 60) {
+            probe += Duration::minutes(1);
+            if let Some(datetime) = self.tz.from_local_datetime(&probe).earliest() {
+                return Some(datetime.with_timezone(&Utc));
+            }
+        }
+        None
+    }
+}
+
+pub fn parse_tz(arg: &str) -> Result<Tz, ArgParserError> {
+    arg.parse()
+        .map_err(|_| ArgParserError::TimezoneParserError(arg.to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn london(policy: DstPolicy) -> TimeContext {
+        TimeContext::new(Some(chrono_tz::Europe::London), policy)
+    }
+
+    fn local(date: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap()
+    }
+
+    fn utc(date: &str) -> DateTime<Utc> {
+        local(date).and_utc()
+    }
+
+    // The clocks went forward from 01:00 GMT to 02:00 BST on 31 March 2024.
+    const MISSING: &str = "2024-03-31 01:30";
+    // The clocks went back from 02:00 BST to 01:00 GMT on 27 October 2024.
+    const AMBIGUOUS: &str = "2024-10-27 01:30";
+
+    #[test]
+    fn missing_time_is_rejected() {
+        let result = london(DstPolicy::Reject).resolve(local(MISSING));
+        assert!(matches!(
+            result,
+            Err(ArgParserError::NonexistentLocalTime(token)) if token == MISSING
+        ));
+    }
+
+    #[test]
+    fn missing_time_moves_to_the_change() {
+        for policy in [DstPolicy::Earliest, DstPolicy::Latest] {
+            let resolved = london(policy).resolve(local(MISSING)).unwrap();
+            assert_eq!(resolved, utc("2024-03-31 01:00"));
+        }
+    }
+
+    #[test]
+    fn ambiguous_time_is_rejected() {
+        let result = london(DstPolicy::Reject).resolve(local(AMBIGUOUS));
+        assert!(matches!(
+            result,
+            Err(ArgParserError::AmbiguousLocalTime(token)) if token == AMBIGUOUS
+        ));
+    }
+
+    #[test]
+    fn ambiguous_time_uses_earliest() {
+        let resolved = london(DstPolicy::Earliest)
+            .resolve(local(AMBIGUOUS))
+            .unwrap();
+        assert_eq!(resolved, utc("2024-10-27 00:30"));
+    }
+
+    #[test]
+    fn ambiguous_time_uses_latest() {
+        let resolved = london(DstPolicy::Latest).resolve(local(AMBIGUOUS)).unwrap();
+        assert_eq!(resolved, utc("2024-10-27 01:30"));
+    }
+
+    #[test]
+    fn start_of_day_on_transition_days() {
+        let ctx = london(DstPolicy::Reject);
+        let spring = NaiveDate::from_ymd_opt(2024, 3, 31).unwrap();
+        let autumn = NaiveDate::from_ymd_opt(2024, 10, 27).unwrap();
+        assert_eq!(ctx.start_of_day(spring), utc("2024-03-31 00:00"));
+        assert_eq!(ctx.start_of_day(autumn), utc("2024-10-26 23:00"));
+    }
+
+    #[test]
+    fn skipped_midnight_starts_day_at_the_change() {
+        // Sao Paulo moved its clocks from midnight to 01:00 on 4 November 2018.
+        let ctx = TimeContext::new(Some(chrono_tz::America::Sao_Paulo), DstPolicy::Reject);
+        let date = NaiveDate::from_ymd_opt(2018, 11, 4).unwrap();
+        assert_eq!(ctx.start_of_day(date), utc("2018-11-04 03:00"));
+    }
+}
@@ -1,5 +1,6 @@
 use std::io;
 use std::path::PathBuf;
+use std::process::ExitCode;
 
 use clap::Parser;
 
@@ -7,13 +8,43 @@ use dev_tracker_core::data::DataStore;
 
 mod cli;
 mod ops;
+mod output;
+mod table;
 
 const APP_NAME: &str = "dev-tracker";
 
-fn main() -> anyhow::Result<()> {
+fn main() -> ExitCode {
     let args = cli::Arguments::parse();
+    let error_format = args.error_format;
 
-    let ds = match args.data_file {
+    let Err(e) = run(args) else {
+        return ExitCode::SUCCESS;
+    };
+
+    let status = cli::ExitStatus::of(&e);
+    match error_format {
+        // Errors found by 'dt' itself are already worded for the user.
+        cli::ErrorFormat::Text if e.is::<cli::CliError>() => eprintln!("{}", e),
+        cli::ErrorFormat::Text => eprintln!("Error: {:?}", e),
+        cli::ErrorFormat::Json => {
+            let error = serde_json::json!({
+                "kind": status.name(),
+                "code": status as u8,
+                "message": e.to_string(),
+            });
+            eprintln!("{}", error);
+        }
+    }
+
+    ExitCode::from(status as u8)
+}
+
+fn run(args: cli::Arguments) -> anyhow::Result<()> {
+    let ctx = cli::TimeContext::new(args.tz, args.dst);
+    let output = args.output;
+    let color = args.color;
+
+    let mut ds = match args.data_file {
         Some(path) => DataStore::new(Some(&path))?,
         None => {
             if !default_file_directory().exists() {
@@ -23,12 +54,29 @@ fn main() -> anyhow::Result<()> {
         }
     };
 
+    // Group the changes made by this run so that 'dt undo' can revert them.
+    let command_line: Vec<_> = std::iter::once("dt".to_string())
+        .chain(std::env::args().skip(1))
+        .collect();
+    ds.begin_operation(&command_line.join(" "))?;
+
     match args.command {
         cli::Command::Add(command) => match command {
             cli::AddCommand::Project(args) => ops::add_project(args, &ds)?,
+            cli::AddCommand::Activity(args) => ops::add_activity(args, &ds, &ctx)?,
             cli::AddCommand::ActivityType(args) => ops::add_activitytype(args, &ds)?,
             cli::AddCommand::Repo(args) => ops::add_repo(args, &ds)?,
         },
+        cli::Command::Archive(command) => match command {
+            cli::ArchiveCommand::Project(args) => ops::archive_project(args, &ds)?,
+            cli::ArchiveCommand::ActivityType(args) => ops::archive_activitytype(args, &ds)?,
+            cli::ArchiveCommand::Repo(args) => ops::archive_repo(args, &ds)?,
+        },
+        cli::Command::Unarchive(command) => match command {
+            cli::UnarchiveCommand::Project(args) => ops::unarchive_project(args, &ds)?,
+            cli::UnarchiveCommand::ActivityType(args) => ops::unarchive_activitytype(args, &ds)?,
+            cli::UnarchiveCommand::Repo(args) => ops::unarchive_repo(args, &ds)?,
+        },
         cli::Command::Delete(command) => match command {
             cli::DeleteCommand::Project(args) => ops::delete_project(args, &ds)?,
             cli::DeleteCommand::Activity(args) => ops::delete_activity(args, &ds)?,
@@ -40,35 +88,85 @@ fn main() -> anyhow::Result<()> {
         cli::Command::Cancel(command) => match command {
             cli::CancelCommand::Activity(args) => ops::cancel_actvity(args, &ds)?,
         },
+        cli::Command::Check(command) => match command {
+            cli::CheckCommand::Overlaps(args) => ops::check_overlaps(args, &ds, &ctx)?,
+        },
         cli::Command::Describe(command) => match command {
-            cli::DescribeCommand::Project(args) => ops::describe_project(args, &ds)?,
-            cli::DescribeCommand::Activity(args) => ops::describe_activity(args, &ds)?,
-            cli::DescribeCommand::Count(args) => ops::describe_count(args, &ds)?,
+            cli::DescribeCommand::Project(args) => ops::describe_project(args, &ds, &ctx, output)?,
+            cli::DescribeCommand::Activity(args) => {
+                ops::describe_activity(args, &ds, &ctx, output)?
+            }
+            cli::DescribeCommand::Count(args) => ops::describe_count(args, &ds, &ctx, output)?,
         },
         cli::Command::List(command) => match command {
-            cli::ListCommand::Projects(args) => ops::list_projects(args, &ds)?,
-            cli::ListCommand::Activities(args) => ops::list_activities(args, &ds)?,
-            cli::ListCommand::ActivityTypes(args) => ops::list_activitytypes(args, &ds)?,
-            cli::ListCommand::Repos(args) => ops::list_repos(args, &ds)?,
-            cli::ListCommand::Counts(args) => ops::list_counts(args, &ds)?,
+            cli::ListCommand::Projects(args) => ops::list_projects(args, &ds, output)?,
+            cli::ListCommand::Activities(args) => {
+                ops::list_activities(args, &ds, &ctx, output, color)?
+            }
+            cli::ListCommand::ActivityTypes(args) => ops::list_activitytypes(args, &ds, output)?,
+            cli::ListCommand::Repos(args) => ops::list_repos(args, &ds, output)?,
+            cli::ListCommand::Counts(args) => ops::list_counts(args, &ds, &ctx, output)?,
+            cli::ListCommand::Tags(args) => ops::list_tags(args, &ds, output)?,
+        },
+        cli::Command::Merge(command) => match command {
+            cli::MergeCommand::Activities(args) => ops::merge_activities(args, &ds)?,
+        },
+        cli::Command::Pause(command) => match command {
+            cli::PauseCommand::Activity(args) => ops::pause_activity(args, &ds)?,
         },
         cli::Command::Rename(command) => match command {
             cli::RenameCommand::Project(args) => ops::rename_project(args, &ds)?,
             cli::RenameCommand::ActivityType(args) => ops::rename_activitytype(args, &ds)?,
         },
+        cli::Command::Resume(mut args) => match args.command.take() {
+            Some(cli::ResumeCommand::Activity(args)) => ops::resume_activity(args, &ds)?,
+            None => ops::resume_last_activity(args, &ds)?,
+        },
+        cli::Command::Set(command) => match command {
+            cli::SetCommand::RunningPolicy(args) => ops::set_running_policy(args, &ds)?,
+        },
+        cli::Command::Split(command) => match command {
+            cli::SplitCommand::Activity(args) => ops::split_activity(args, &ds, &ctx)?,
+        },
         cli::Command::Start(command) => match command {
-            cli::StartCommand::Activity(args) => ops::start_activity(args, &ds)?,
+            cli::StartCommand::Activity(args) => ops::start_activity(args, &ds, &ctx)?,
         },
         cli::Command::Stop(command) => match command {
-            cli::StopCommand::Activity(args) => ops::stop_activity(args, &ds)?,
+            cli::StopCommand::Activity(args) => ops::stop_activity(args, &ds, &ctx)?,
+        },
+        cli::Command::Switch(args) => ops::switch_activity(args, &ds)?,
+        cli::Command::Tag(command) => match command {
+            cli::TagCommand::Activity(args) => ops::tag_activity(args, &ds)?,
+            cli::TagCommand::Project(args) => ops::tag_project(args, &ds)?,
+        },
+        cli::Command::Untag(command) => match command {
+            cli::UntagCommand::Activity(args) => ops::untag_activity(args, &ds)?,
+            cli::UntagCommand::Project(args) => ops::untag_project(args, &ds)?,
         },
         cli::Command::Update(command) => match command {
+            cli::UpdateCommand::Project(args) => match args.command {
+                cli::UpdateProjectCommand::Description(args) => {
+                    ops::update_project_description(args, &ds)?
+                }
+                cli::UpdateProjectCommand::Status(args) => ops::update_project_status(args, &ds)?,
+                cli::UpdateProjectCommand::Created(args) => {
+                    ops::update_project_created(args, &ds, &ctx)?
+                }
+                cli::UpdateProjectCommand::Target(args) => {
+                    ops::update_project_target(args, &ds, &ctx)?
+                }
+                cli::UpdateProjectCommand::Url(args) => ops::update_project_url(args, &ds)?,
+                cli::UpdateProjectCommand::Parent(args) => ops::update_project_parent(args, &ds)?,
+            },
             cli::UpdateCommand::ActivityType(args) => ops::update_activitytype(args, &ds)?,
             cli::UpdateCommand::Activity(args) => match args.command {
                 cli::UpdateActivityCommand::Description(args) => {
                     ops::update_activity_description(args, &ds)?
                 }
-                cli::UpdateActivityCommand::End(args) => ops::update_activity_end(args, &ds)?,
+                cli::UpdateActivityCommand::Start(args) => {
+                    ops::update_activity_start(args, &ds, &ctx)?
+                }
+                cli::UpdateActivityCommand::End(args) => ops::update_activity_end(args, &ds, &ctx)?,
                 cli::UpdateActivityCommand::Project(args) => {
                     ops::update_activity_project(args, &ds)?
                 }
@@ -79,10 +177,18 @@ fn main() -> anyhow::Result<()> {
             cli::UpdateCommand::Repo(args) => ops::update_repo(args, &ds)?,
         },
         cli::Command::Generate(command) => match command {
-            cli::GenerateCommand::Report(args) => ops::generate_report(args, &ds)?,
-            cli::GenerateCommand::Json(args) => ops::generate_json(args, &ds)?,
+            cli::GenerateCommand::Report(args) => ops::generate_report(args, &ds, &ctx)?,
+            cli::GenerateCommand::Json(args) => ops::generate_json(args, &ds, &ctx)?,
+        },
+        cli::Command::Status(args) => ops::show_status(args, &ds, &ctx, output)?,
+        cli::Command::Db(command) => match command {
+            cli::DbCommand::Backup(args) => ops::backup(args, &ds)?,
+            cli::DbCommand::Restore(args) => ops::restore(args, &mut ds)?,
+            cli::DbCommand::Vacuum => ops::vacuum(&ds)?,
+            cli::DbCommand::Check(args) => ops::check_database(args, &ds)?,
         },
-        cli::Command::Status(args) => ops::show_status(args, &ds)?,
+        cli::Command::History(args) => ops::show_history(args, &ds, &ctx)?,
+        cli::Command::Undo(args) => ops::undo(args, &ds)?,
     }
 
     Ok(())
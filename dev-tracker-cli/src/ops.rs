@@ -1,21 +1,49 @@
-use std::process;
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
 
-use chrono::{DateTime, Local, Utc};
+use anyhow::bail;
+use chrono::{DateTime, Duration, NaiveDate, Utc};
 
 use dev_tracker_core::data::DataStore;
+use dev_tracker_core::model::{Activity, Project, ProjectStatus};
+use dev_tracker_core::overlap::{Overlap, OverlapResolution};
+use dev_tracker_core::query::{ActivityOrder, ActivityQuery};
+use dev_tracker_core::settings::RunningPolicy;
+use dev_tracker_core::Error;
+use serde::Serialize;
 
 use crate::cli::{
-    AddActivityTypeArgs, AddProjectArgs, AddRepoArgs, CancelActivityTypeArgs, CountCommandArgs,
+    ActivitySort, AddActivityArgs, AddActivityTypeArgs, AddProjectArgs, AddRepoArgs,
+    ArchiveActivityTypeArgs, ArchiveProjectArgs, ArchiveRepoArgs, CancelActivityTypeArgs,
+    CheckOverlapsArgs, CliError, ColorChoice, CountCommandArgs, DbCheckArgs, DbFileArgs,
     DeleteActivityArgs, DeleteActivityTypeArgs, DeleteCountArgs, DeleteProjectArgs, DeleteRepoArgs,
-    DescribeActivityArgs, DescribeCountArgs, DescribeProjectArgs, GenerateArgs, ListActivityArgs,
-    ListActivityTypeArgs, ListCountArgs, ListProjectArgs, ListRepoArgs, RenameActivityTypeArgs,
-    RenameProjectArgs, StartActivityArgs, StatusArgs, StopActivityArgs,
+    DescribeActivityArgs, DescribeCountArgs, DescribeProjectArgs, GenerateArgs, HistoryArgs,
+    ListActivityArgs, ListActivityTypeArgs, ListCountArgs, ListProjectArgs, ListRepoArgs,
+    ListTagArgs, MergeActivitiesArgs, OutputFormat, OverlapFix, PauseActivityArgs,
+    RenameActivityTypeArgs, RenameProjectArgs, ResumeActivityArgs, ResumeArgs,
+    SetRunningPolicyArgs, SplitActivityArgs, StartActivityArgs, StatusArgs, StopActivityArgs,
+    SwitchArgs, TagActivityArgs, TagProjectArgs, TimeArg, TimeContext, UndoArgs,
     UpdateActivityActivityTypeArgs, UpdateActivityDescriptionArgs, UpdateActivityEndArgs,
-    UpdateActivityProjectArgs, UpdateActivityTypeArgs, UpdateRepoArgs,
+    UpdateActivityProjectArgs, UpdateActivityStartArgs, UpdateActivityTypeArgs,
+    UpdateProjectCreatedArgs, UpdateProjectDescriptionArgs, UpdateProjectParentArgs,
+    UpdateProjectStatusArgs, UpdateProjectTargetArgs, UpdateProjectUrlArgs, UpdateRepoArgs,
 };
+use crate::output::{print_record, print_records};
+use crate::table::{Align, Cell, Style, Table, Terminal};
 
 pub fn add_project(args: AddProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
-    ds.create_project(&args.name)?;
+    match args.parent {
+        Some(parent) => {
+            let Some(parent) = ds.get_project(&parent)? else {
+                bail!(CliError::NotFound(format!(
+                    "Add failed, no such parent project: {}",
+                    parent
+                )));
+            };
+            ds.create_child_project(&args.name, &parent)?
+        }
+        None => ds.create_project(&args.name)?,
+    };
 
     if let Some(path) = args.path {
         let project = ds
@@ -27,6 +55,42 @@ pub fn add_project(args: AddProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
     Ok(())
 }
 
+pub fn add_activity(
+    args: AddActivityArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
+    let Some(project) = ds.get_project(&args.project)? else {
+        bail!(CliError::NotFound(format!(
+            "Add failed, no such project: {}",
+            args.project
+        )));
+    };
+
+    let Some(at) = ds.get_activitytype(&args.activity_type)? else {
+        bail!(CliError::NotFound(format!(
+            "Add failed, no such activity type: {}",
+            args.activity_type
+        )));
+    };
+
+    let start = args.start.datetime(ctx)?;
+    let end = args.end.datetime(ctx)?;
+    if end < start {
+        let local_start = ctx.local(start);
+        let local_end = ctx.local(end);
+        bail!(CliError::Invalid(format!(
+            "Add failed, end time {} is before start time {}",
+            local_end.format("%I:%M%P on %A %d %B %Y"),
+            local_start.format("%I:%M%P on %A %d %B %Y")
+        )));
+    }
+
+    ds.create_activity(&project, &at, args.description, &args.tags, start, end)?;
+
+    Ok(())
+}
+
 pub fn add_activitytype(args: AddActivityTypeArgs, ds: &DataStore) -> anyhow::Result<()> {
     ds.create_activitytype(&args.name, args.description)?;
 
@@ -35,8 +99,10 @@ pub fn add_activitytype(args: AddActivityTypeArgs, ds: &DataStore) -> anyhow::Re
 
 pub fn add_repo(args: AddRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("Add failed, no such project: {}", args.project);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Add failed, no such project: {}",
+            args.project
+        )));
     };
 
     ds.create_repo(&project, &args.path)?;
@@ -46,13 +112,15 @@ pub fn add_repo(args: AddRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
 
 pub fn count(args: CountCommandArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("Cancel failed, no such project: {}", args.project);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Count failed, no such project: {}",
+            args.project
+        )));
     };
 
-    let repos = ds.get_repos(&project)?;
+    let repos = skip_corrupt(ds.get_repos(&project)?)?;
 
-    for repo in repos.iter() {
+    for repo in repos.iter().filter(|r| !r.is_archived()) {
         let _count = ds.create_count(repo)?;
     }
 
@@ -61,8 +129,10 @@ pub fn count(args: CountCommandArgs, ds: &DataStore) -> anyhow::Result<()> {
 
 pub fn cancel_actvity(args: CancelActivityTypeArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("Cancel failed, no such project: {}", args.project);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Cancel failed, no such project: {}",
+            args.project
+        )));
     };
 
     ds.cancel_running_actvity(&project)?;
@@ -70,21 +140,219 @@ pub fn cancel_actvity(args: CancelActivityTypeArgs, ds: &DataStore) -> anyhow::R
     Ok(())
 }
 
+pub fn check_overlaps(
+    args: CheckOverlapsArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
+    let project = match args.project {
+        Some(name) => {
+            let Some(project) = ds.get_project(&name)? else {
+                bail!(CliError::NotFound(format!(
+                    "Check overlaps failed, no such project: {}",
+                    name
+                )));
+            };
+            Some(project)
+        }
+        None => None,
+    };
+
+    let Some(fix) = args.fix else {
+        let overlaps = ds.find_overlaps(project.as_ref())?;
+        for overlap in overlaps.iter() {
+            println!("{}", describe_overlap(overlap, ds, ctx)?);
+        }
+        if overlaps.is_empty() {
+            println!("No overlapping activities");
+        }
+        return Ok(());
+    };
+
+    ds.create_rotating_backup("fix")?;
+    let resolution = match fix {
+        OverlapFix::Trim => OverlapResolution::Trim,
+        OverlapFix::Merge => OverlapResolution::Merge,
+        OverlapFix::Split => OverlapResolution::Split,
+    };
+
+    // Resolving one overlap changes the activities involved in others, so
+    // look for overlaps again after each change.  Every resolution removes at
+    // least one overlapping pair so this always finishes.  A merge that would
+    // extend an activity over another one is put off until another overlap
+    // has been resolved, which may make it possible.
+    let mut skipped = Vec::new();
+    let mut deferred = Vec::new();
+    loop {
+        let overlaps = ds.find_overlaps(project.as_ref())?;
+        let Some(overlap) = overlaps.into_iter().find(|o| {
+            let pair = (o.first().id(), o.second().id());
+            !skipped.contains(&pair) && !deferred.iter().any(|(p, _)| *p == pair)
+        }) else {
+            break;
+        };
+        let pair = (overlap.first().id(), overlap.second().id());
+
+        if resolution == OverlapResolution::Merge {
+            let reason = if !overlap.same_project() {
+                Some("they belong to different projects")
+            } else if !overlap.same_activity_type() {
+                Some("they have different activity types")
+            } else {
+                None
+            };
+            if let Some(reason) = reason {
+                println!(
+                    "Skipped merging activities {} and {}, {}",
+                    pair.0, pair.1, reason
+                );
+                skipped.push(pair);
+                continue;
+            }
+        }
+
+        let created = match ds.resolve_overlap(&overlap, resolution) {
+            Ok(created) => created,
+            Err(Error::ActivityOverlaps(other)) if resolution == OverlapResolution::Merge => {
+                deferred.push((pair, other));
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+        deferred.clear();
+        let verb = match resolution {
+            OverlapResolution::Trim => "Trimmed",
+            OverlapResolution::Merge => "Merged",
+            OverlapResolution::Split => "Split",
+        };
+        print!(
+            "{} activities {} and {}",
+            verb,
+            overlap.first().id(),
+            overlap.second().id()
+        );
+        match created {
+            Some(activity) => println!(", created activity {}", activity.id()),
+            None => println!(),
+        }
+    }
+
+    for ((first, second), other) in deferred {
+        println!(
+            "Skipped merging activities {} and {}, the merged activity would overlap activity {}",
+            first, second, other
+        );
+    }
+
+    Ok(())
+}
+
+pub fn archive_project(args: ArchiveProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Archive failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    ds.archive_project(&project)?;
+
+    Ok(())
+}
+
+pub fn archive_activitytype(args: ArchiveActivityTypeArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(at) = ds.get_activitytype(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Archive failed, no such activity type: {}",
+            args.name
+        )));
+    };
+
+    ds.archive_activitytype(&at)?;
+
+    Ok(())
+}
+
+pub fn archive_repo(args: ArchiveRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(repo) = ds.get_repo(&args.path)? else {
+        bail!(CliError::NotFound(format!(
+            "Archive failed, no such repository with path: {}",
+            args.path.display()
+        )));
+    };
+
+    ds.archive_repo(&repo)?;
+
+    Ok(())
+}
+
+pub fn unarchive_project(args: ArchiveProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Unarchive failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    ds.unarchive_project(&project)?;
+
+    Ok(())
+}
+
+pub fn unarchive_activitytype(args: ArchiveActivityTypeArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(at) = ds.get_activitytype(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Unarchive failed, no such activity type: {}",
+            args.name
+        )));
+    };
+
+    ds.unarchive_activitytype(&at)?;
+
+    Ok(())
+}
+
+pub fn unarchive_repo(args: ArchiveRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(repo) = ds.get_repo(&args.path)? else {
+        bail!(CliError::NotFound(format!(
+            "Unarchive failed, no such repository with path: {}",
+            args.path.display()
+        )));
+    };
+
+    ds.unarchive_repo(&repo)?;
+
+    Ok(())
+}
+
 pub fn delete_project(args: DeleteProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.name)? else {
-        eprintln!("Delete failed, no such project: {}", args.name);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Delete failed, no such project: {}",
+            args.name
+        )));
     };
 
-    ds.delete_project(project)?;
+    if args.purge {
+        ds.create_rotating_backup("purge")?;
+        ds.delete_project(project)?;
+    } else {
+        ds.archive_project(&project)?;
+        println!(
+            "Archived project {}, use '--purge' to delete it permanently",
+            project.name()
+        );
+    }
 
     Ok(())
 }
 
 pub fn delete_activity(args: DeleteActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(activity) = ds.get_activity_with_id(args.id)? else {
-        eprintln!("Delete failed, no such activity: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Delete failed, no such activity: {}",
+            args.id
+        )));
     };
 
     ds.delete_activity(activity)?;
@@ -94,19 +362,32 @@ pub fn delete_activity(args: DeleteActivityArgs, ds: &DataStore) -> anyhow::Resu
 
 pub fn delete_activitytype(args: DeleteActivityTypeArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(at) = ds.get_activitytype(&args.name)? else {
-        eprintln!("Delete failed, no such activity type: {}", args.name);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Delete failed, no such activity type: {}",
+            args.name
+        )));
     };
 
-    ds.delete_activitytype(at)?;
+    if args.purge {
+        ds.create_rotating_backup("purge")?;
+        ds.delete_activitytype(at)?;
+    } else {
+        ds.archive_activitytype(&at)?;
+        println!(
+            "Archived activity type {}, use '--purge' to delete it permanently",
+            at.name()
+        );
+    }
 
     Ok(())
 }
 
 pub fn delete_count(args: DeleteCountArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(count) = ds.get_count_with_id(args.id)? else {
-        eprintln!("Delete failed, no such count: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Delete failed, no such count: {}",
+            args.id
+        )));
     };
 
     ds.delete_count(count)?;
@@ -116,27 +397,114 @@ pub fn delete_count(args: DeleteCountArgs, ds: &DataStore) -> anyhow::Result<()>
 
 pub fn delete_repo(args: DeleteRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(repo) = ds.get_repo(&args.path)? else {
-        eprintln!("Delete failed, no such repository with path: {}", args.path.display());
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Delete failed, no such repository with path: {}",
+            args.path.display()
+        )));
     };
 
-    ds.delete_repo(repo)?;
+    if args.purge {
+        ds.create_rotating_backup("purge")?;
+        ds.delete_repo(repo)?;
+    } else {
+        ds.archive_repo(&repo)?;
+        println!(
+            "Archived repository {}, use '--purge' to delete it permanently",
+            repo.path().display()
+        );
+    }
 
     Ok(())
 }
 
-pub fn describe_project(args: DescribeProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
+// The record written by 'dt describe project' in the machine-readable output
+// formats, the project followed by its totals.
+#[derive(Serialize)]
+struct ProjectSummary<'a> {
+    id: u64,
+    name: &'a str,
+    description: Option<&'a str>,
+    status: ProjectStatus,
+    created: DateTime<Utc>,
+    target: Option<NaiveDate>,
+    url: Option<&'a str>,
+    parent: Option<u64>,
+    activities: usize,
+    worked_minutes: i64,
+    lines_of_code: u64,
+}
+
+impl<'a> ProjectSummary<'a> {
+    fn new(project: &'a Project, ds: &DataStore) -> anyhow::Result<Self> {
+        let activities = skip_corrupt(ds.get_activities(project)?)?;
+        let mut lines_of_code = 0;
+        for repo in skip_corrupt(ds.get_repos(project)?)?.iter() {
+            if let Some(count) = skip_corrupt(ds.get_latest_count(repo).transpose())?.pop() {
+                lines_of_code += ds.get_total_loc(&count)?;
+            }
+        }
+
+        Ok(Self {
+            id: project.id(),
+            name: project.name(),
+            description: project.description(),
+            status: project.status(),
+            created: project.created(),
+            target: project.target(),
+            url: project.url(),
+            parent: project.parent(),
+            activities: activities.len(),
+            worked_minutes: total_worked(&activities).num_minutes(),
+            lines_of_code,
+        })
+    }
+}
+
+pub fn describe_project(
+    args: DescribeProjectArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.name)? else {
-        eprintln!("Describe failed, no such project: {}", args.name);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Describe failed, no such project: {}",
+            args.name
+        )));
     };
 
+    if output != OutputFormat::Text {
+        return print_record(&ProjectSummary::new(&project, ds)?, output);
+    }
+
     println!("Project name '{}'", project.name());
+    if let Some(description) = project.description() {
+        println!("Description '{}'", description);
+    }
+    println!("Status {}", project.status());
+    println!(
+        "Created on {}",
+        ctx.local(project.created()).format("%A %d %B %Y")
+    );
+    if let Some(target) = project.target() {
+        println!("Target date {}", target.format("%A %d %B %Y"));
+    }
+    if let Some(url) = project.url() {
+        println!("URL {}", url);
+    }
+    if let Some(parent) = project.parent() {
+        if let Some(parent) = ds.get_project_with_id(parent)? {
+            println!("Sub-project of '{}'", parent.name());
+        }
+    }
+    for child in skip_corrupt(ds.get_child_projects(&project)?)? {
+        println!("Sub-project '{}'", child.name());
+    }
 
-    let repos = ds.get_repos(&project)?;
+    let repos = skip_corrupt(ds.get_repos(&project)?)?;
     for repo in repos.iter() {
         println!("Repository path '{}'", repo.path().display());
-        if let Some(count) = ds.get_latest_count(repo)? {
+        if let Some(count) = skip_corrupt(ds.get_latest_count(repo).transpose())?.pop() {
             println!("  {} lines of code", ds.get_total_loc(&count)?)
         }
     }
@@ -144,14 +512,37 @@ pub fn describe_project(args: DescribeProjectArgs, ds: &DataStore) -> anyhow::Re
         println!("No repositories")
     }
 
-    let activities = ds.get_activities(&project)?;
+    let activities = skip_corrupt(ds.get_activities(&project)?)?;
     if activities.is_empty() {
         println!("No activities")
     } else {
         println!("Total activity count {}", activities.len());
+        println!("Total time {}", duration_to_str(total_worked(&activities)));
     }
 
-    let ats = ds.get_activitytypes()?;
+    // Time and lines of code from sub-projects are rolled up into the parent.
+    let descendants = skip_corrupt(ds.get_descendant_projects(&project)?)?;
+    if !descendants.is_empty() {
+        let mut query = ActivityQuery::new();
+        let mut loc = 0;
+        for p in std::iter::once(&project).chain(descendants.iter()) {
+            query = query.project(p);
+            for repo in skip_corrupt(ds.get_repos(p)?)?.iter() {
+                if let Some(count) = skip_corrupt(ds.get_latest_count(repo).transpose())?.pop() {
+                    loc += ds.get_total_loc(&count)?;
+                }
+            }
+        }
+        let all_activities = skip_corrupt(ds.query_activities(&query)?)?;
+        println!(
+            "Including sub-projects, total activity count {}, total time {}, {} lines of code",
+            all_activities.len(),
+            duration_to_str(total_worked(&all_activities)),
+            loc
+        );
+    }
+
+    let ats = skip_corrupt(ds.get_activitytypes()?)?;
     if !ats.is_empty() && !activities.is_empty() {
         for at in ats.iter() {
             let count = activities.iter().filter(|a| a.atype() == at.id()).count();
@@ -169,33 +560,45 @@ pub fn describe_project(args: DescribeProjectArgs, ds: &DataStore) -> anyhow::Re
     Ok(())
 }
 
-pub fn describe_activity(args: DescribeActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn describe_activity(
+    args: DescribeActivityArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
     let Some(activity) = ds.get_activity_with_id(args.id)? else {
-        eprintln!("Describe failed, no such activity: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Describe failed, no such activity: {}",
+            args.id
+        )));
     };
 
     let Some(at) = ds.get_activitytype_with_id(activity.atype())? else {
-        eprintln!(
+        bail!(CliError::NotFound(format!(
             "Describe failed, no activity type for activity: {}",
             args.id
-        );
-        process::exit(1);
+        )));
     };
 
     let Some(project) = ds.get_project_with_id(activity.project())? else {
-        eprintln!("Describe failed, no project for activity: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Describe failed, no project for activity: {}",
+            args.id
+        )));
     };
 
+    if output != OutputFormat::Text {
+        return print_record(&activity, output);
+    }
+
     println!("Project: {}", project.name());
     println!("Activity type: {}", at.name());
 
-    let local_start: DateTime<Local> = DateTime::from(activity.start_time());
+    let local_start = ctx.local(activity.start_time());
     println!("Started: {}", local_start.format("%I:%M%P on %A %d %B %Y"));
 
     if let Some(end) = activity.end_time() {
-        let local_end: DateTime<Local> = DateTime::from(end);
+        let local_end = ctx.local(end);
         println!("Finished: {}", local_end.format("%I:%M%P on %A %d %B %Y"));
         let minutes = activity
             .duration()
@@ -208,43 +611,196 @@ pub fn describe_activity(args: DescribeActivityArgs, ds: &DataStore) -> anyhow::
             println!("minutes");
         }
     } else {
-        println!("Finished: still running");
+        println!("Finished: still running{}", paused_phrase(&activity, ctx));
+    }
+
+    let paused = activity.paused_until(Utc::now()).num_minutes();
+    if paused > 0 {
+        print!("Paused: {} ", paused);
+        if paused == 1 {
+            println!("minute");
+        } else {
+            println!("minutes");
+        }
     }
 
     Ok(())
 }
 
-pub fn describe_count(args: DescribeCountArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn describe_count(
+    args: DescribeCountArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
     let Some(count) = ds.get_count_with_id(args.id)? else {
-        eprintln!("Describe failed, no such count: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Describe failed, no such count: {}",
+            args.id
+        )));
     };
 
     let Some(repo) = ds.get_repo_with_id(count.repo())? else {
-        eprintln!("Describe failed, no such repository: {}", count.repo());
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Describe failed, no such repository: {}",
+            count.repo()
+        )));
     };
 
     let Some(project) = ds.get_project_with_id(repo.project())? else {
-        eprintln!("Describe failed, no such project: {}", repo.project());
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Describe failed, no such project: {}",
+            repo.project()
+        )));
     };
 
+    if output != OutputFormat::Text {
+        return print_record(&count, output);
+    }
+
     println!("Project: {}", project.name());
     println!("Repository: {}", repo.path().display());
-    println!("Date: {}", count.date().format("%A %d %B %Y at %I:%M%P"));
+    println!(
+        "Date: {}",
+        ctx.local(count.date()).format("%A %d %B %Y at %I:%M%P")
+    );
     println!("Lines of code: {}", ds.get_total_loc(&count)?);
 
     Ok(())
 }
 
-pub fn list_projects(args: ListProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
-    let projects = ds.get_projects()?;
-    for project in projects.iter() {
+pub fn backup(args: DbFileArgs, ds: &DataStore) -> anyhow::Result<()> {
+    ds.backup(&args.file)?;
+
+    Ok(())
+}
+
+pub fn restore(args: DbFileArgs, ds: &mut DataStore) -> anyhow::Result<()> {
+    ds.restore(&args.file)?;
+
+    Ok(())
+}
+
+pub fn vacuum(ds: &DataStore) -> anyhow::Result<()> {
+    ds.vacuum()?;
+
+    Ok(())
+}
+
+pub fn check_database(args: DbCheckArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let issues = ds.check_integrity()?;
+    if issues.is_empty() {
+        println!("No problems found");
+        return Ok(());
+    }
+
+    if !args.fix {
+        for issue in issues.iter() {
+            println!("{}", issue);
+        }
+        bail!(CliError::ProblemsFound(format!(
+            "Found {} problems, use 'dt db check --fix' to repair them",
+            issues.len()
+        )));
+    }
+
+    ds.create_rotating_backup("fix")?;
+    let mut remaining = 0;
+    for issue in issues.iter() {
+        if issue.is_fixable() {
+            ds.fix_issue(issue)?;
+            println!("Fixed: {}", issue);
+        } else {
+            println!("Cannot fix: {}", issue);
+            remaining += 1;
+        }
+    }
+
+    if remaining > 0 {
+        bail!(CliError::ProblemsFound(format!(
+            "{} problems could not be fixed, restore the database from a backup",
+            remaining
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn show_history(args: HistoryArgs, ds: &DataStore, ctx: &TimeContext) -> anyhow::Result<()> {
+    let operations = ds.get_history(args.count)?;
+    for operation in operations.iter() {
+        let changes = operation.changes().len();
+        print!(
+            "{}. {} {}, {} {}",
+            operation.id(),
+            ctx.local(operation.time()).format("%Y-%m-%d %H:%M"),
+            operation.description().unwrap_or("unknown command"),
+            changes,
+            if changes == 1 { "change" } else { "changes" }
+        );
+        if operation.is_undone() {
+            print!(" (undone)");
+        }
+        println!();
+
         if args.verbose {
-            print!("{}. ", project.id());
+            for change in operation.changes() {
+                print!("  {} {} {}", change.action(), change.table(), change.row());
+                let columns = change.changed_columns();
+                if !columns.is_empty() {
+                    print!(": {}", columns.join(", "));
+                }
+                println!();
+            }
         }
-        println!("{}", project.name());
+    }
+
+    if operations.is_empty() {
+        println!("No changes in history");
+    }
+
+    Ok(())
+}
+
+pub fn undo(args: UndoArgs, ds: &DataStore) -> anyhow::Result<()> {
+    ds.create_rotating_backup("undo")?;
+    let operations = ds.undo(args.count)?;
+    for operation in operations.iter() {
+        println!(
+            "Undid {}. {}",
+            operation.id(),
+            operation.description().unwrap_or("unknown command")
+        );
+    }
+
+    if operations.is_empty() {
+        println!("Nothing to undo");
+    }
+
+    Ok(())
+}
+
+pub fn list_projects(
+    args: ListProjectArgs,
+    ds: &DataStore,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let projects: Vec<_> = skip_corrupt(ds.get_projects()?)?
+        .into_iter()
+        .filter(|p| args.all || p.status() != ProjectStatus::Archived)
+        .collect();
+    if output != OutputFormat::Text {
+        return print_records(&projects, output);
+    }
+
+    // Projects whose parent is not listed, for example because it has been
+    // archived, are shown at the top level.
+    let roots = projects.iter().filter(|p| {
+        p.parent()
+            .is_none_or(|id| projects.iter().all(|q| q.id() != id))
+    });
+    for project in roots {
+        print_project_tree(project, &projects, 0, args.verbose);
     }
 
     if projects.is_empty() {
@@ -254,79 +810,235 @@ pub fn list_projects(args: ListProjectArgs, ds: &DataStore) -> anyhow::Result<()
     Ok(())
 }
 
-pub fn list_activities(args: ListActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
-    let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("List activities failed, no such project: {}", args.project);
-        process::exit(1);
-    };
+fn print_project_tree(project: &Project, projects: &[Project], depth: usize, verbose: bool) {
+    print!("{}", "  ".repeat(depth));
+    if verbose {
+        print!("{}. ", project.id());
+    }
+    if project.status() == ProjectStatus::Active {
+        println!("{}", project.name());
+    } else {
+        println!("{} ({})", project.name(), project.status());
+    }
+
+    for child in projects.iter().filter(|p| p.parent() == Some(project.id())) {
+        print_project_tree(child, projects, depth + 1, verbose);
+    }
+}
 
-    let activities = ds.get_activities(&project)?;
+pub fn list_activities(
+    args: ListActivityArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+    output: OutputFormat,
+    color: ColorChoice,
+) -> anyhow::Result<()> {
+    let mut query = ActivityQuery::new()
+        .order_by(match args.sort {
+            ActivitySort::Start => ActivityOrder::Start,
+            ActivitySort::Duration => ActivityOrder::Duration,
+            ActivitySort::Type => ActivityOrder::ActivityType,
+        })
+        .reverse(args.reverse);
+    let mut project = None;
+    if let Some(name) = &args.project {
+        let Some(found) = ds.get_project(name)? else {
+            bail!(CliError::NotFound(format!(
+                "List activities failed, no such project: {}",
+                name
+            )));
+        };
+        query = query.project(&found);
+        project = Some(found);
+    }
+    if let Some(name) = &args.activity_type {
+        let Some(at) = ds.get_activitytype(name)? else {
+            bail!(CliError::NotFound(format!(
+                "List activities failed, no such activity type: {}",
+                name
+            )));
+        };
+        query = query.activitytype(&at);
+    }
+    if let Some(tag) = &args.tag {
+        query = query.tag(tag);
+    }
+    if let Some(since) = &args.since {
+        query = query.since(since.datetime(ctx)?);
+    }
+    if let Some(until) = &args.until {
+        query = query.until(until.end_date(ctx)?);
+    }
+    if args.running {
+        query = query.running(true);
+    }
+    if let Some(limit) = args.limit {
+        query = query.limit(limit);
+    }
+    if output != OutputFormat::Text {
+        return print_records(&skip_corrupt(ds.query_activities(&query)?)?, output);
+    }
+
+    let activities = skip_corrupt(ds.query_activities(&query)?)?;
+    if activities.is_empty() {
+        match project {
+            Some(project) => println!("No activities for project {} in database", project.name()),
+            None => println!("No activities in database"),
+        }
+        return Ok(());
+    }
+
+    let mut activity_tags = ds.get_tags_for_activities(&activities)?;
+
+    // The name and tags of each project, looked up once.
+    let mut projects: BTreeMap<u64, (String, Vec<String>)> = BTreeMap::new();
+    let mut tag_totals: BTreeMap<String, Duration> = BTreeMap::new();
+    let mut type_names: BTreeMap<u64, String> = BTreeMap::new();
+    let mut rows = Vec::new();
     for activity in activities.iter() {
-        let at = ds
-            .get_activitytype_with_id(activity.atype())?
-            .expect("should always be able to get an activity type");
+        if let Entry::Vacant(entry) = type_names.entry(activity.atype()) {
+            let at = ds
+                .get_activitytype_with_id(activity.atype())?
+                .expect("should always be able to get an activity type");
+            entry.insert(at.name().to_string());
+        }
 
-        if args.verbose {
-            print!("{}. ", activity.id());
-        }
-        print!("{} ", at.name());
-
-        let local_start: DateTime<Local> = DateTime::from(activity.start_time());
-        if let Some(end) = activity.end_time() {
-            let local_end: DateTime<Local> = DateTime::from(end);
-            let dur = activity
-                .duration()
-                .expect("we have an end so we should have a duration");
-            let hours = dur.num_hours();
-            let minutes = dur.num_minutes() % 60;
-
-            let hours = match hours {
-                0 => String::new(),
-                1 => "1 hour".to_string(),
-                h => format!("{} hours", h),
-            };
+        if let Entry::Vacant(entry) = projects.entry(activity.project()) {
+            let project = ds
+                .get_project_with_id(activity.project())?
+                .expect("should always be able to get a project");
+            let tags = ds
+                .get_project_tags(&project)?
+                .iter()
+                .map(|t| t.name().to_string())
+                .collect();
+            entry.insert((project.name().to_string(), tags));
+        }
+        let (project_name, project_tags) = &projects[&activity.project()];
 
-            let minutes = match minutes {
-                0 => String::new(),
-                1 => "1 minute".to_string(),
-                m => format!("{} minutes", m),
-            };
+        let mut tags: Vec<_> = activity_tags
+            .remove(&activity.id())
+            .unwrap_or_default()
+            .iter()
+            .map(|t| t.name().to_string())
+            .collect();
+        for tag in project_tags {
+            if !tags.contains(tag) {
+                tags.push(tag.clone());
+            }
+        }
+        tags.sort();
+        let worked = activity.worked_until(activity.end_time().unwrap_or_else(Utc::now));
+        for tag in &tags {
+            *tag_totals.entry(tag.clone()).or_insert_with(Duration::zero) += worked;
+        }
 
-            let duration = match (hours.is_empty(), minutes.is_empty()) {
-                (false, false) => format!("{} {}", hours, minutes),
-                (true, false) => minutes,
-                (false, true) => hours,
-                (true, true) => "less than a minutes".to_string(),
-            };
+        let end = match activity.end_time() {
+            Some(end) => Cell::from(ctx.local(end).format("%Y-%m-%d %H:%M").to_string()),
+            None if activity.is_paused() => Cell::styled("paused", Style::Yellow),
+            None => Cell::styled("running", Style::Green),
+        };
+        rows.push((
+            vec![
+                Cell::from(activity.id().to_string()),
+                Cell::from(project_name.as_str()),
+                Cell::from(type_names[&activity.atype()].as_str()),
+                Cell::from(
+                    ctx.local(activity.start_time())
+                        .format("%Y-%m-%d %H:%M")
+                        .to_string(),
+                ),
+                end,
+                Cell::from(duration_to_short_str(worked)),
+                Cell::from(activity.description().unwrap_or_default()),
+            ],
+            tags,
+        ));
+    }
 
-            println!(
-                "from {} until {}, total time {}",
-                local_start.format("%I:%M%P on %A %d %B %Y"),
-                local_end.format("%I:%M%P on %A %d %B %Y"),
-                duration
-            )
-        } else {
-            println!(
-                "started at {}, and is still running",
-                local_start.format("%I:%M%P on %A %d %B %Y")
-            )
+    let show_tags = rows.iter().any(|(_, tags)| !tags.is_empty());
+    let mut table = Table::new()
+        .column("ID", Align::Right, false)
+        .column("Project", Align::Left, !args.verbose)
+        .column("Type", Align::Left, !args.verbose)
+        .column("Start", Align::Left, false)
+        .column("End", Align::Left, false)
+        .column("Duration", Align::Right, false)
+        .column("Description", Align::Left, !args.verbose);
+    if show_tags {
+        table = table.column("Tags", Align::Left, !args.verbose);
+    }
+    for (mut cells, tags) in rows {
+        if show_tags {
+            cells.push(Cell::from(tags.join(", ")));
+        }
+        table.row(cells);
+    }
+    let count = match activities.len() {
+        1 => "1 activity".to_string(),
+        n => format!("{} activities", n),
+    };
+    table.footer(vec![
+        Cell::default(),
+        Cell::from("Total"),
+        Cell::default(),
+        Cell::from(count),
+        Cell::default(),
+        Cell::from(duration_to_short_str(total_worked(&activities))),
+    ]);
+    table.print(&Terminal::detect(color));
+
+    if !tag_totals.is_empty() {
+        println!();
+        println!("Total time by tag:");
+        for (tag, total) in tag_totals {
+            println!("  {} {}", tag, duration_to_str(total));
         }
     }
 
-    if activities.is_empty() {
-        println!("No activities for project {} in database", project.name());
+    Ok(())
+}
+
+pub fn list_tags(args: ListTagArgs, ds: &DataStore, output: OutputFormat) -> anyhow::Result<()> {
+    let tags = skip_corrupt(ds.get_tags()?)?;
+    if output != OutputFormat::Text {
+        return print_records(&tags, output);
+    }
+    for tag in tags.iter() {
+        if args.verbose {
+            print!("{}. ", tag.id());
+        }
+        println!("{}", tag.name());
+    }
+
+    if tags.is_empty() {
+        println!("No tags in database");
     }
 
     Ok(())
 }
 
-pub fn list_activitytypes(args: ListActivityTypeArgs, ds: &DataStore) -> anyhow::Result<()> {
-    let ats = ds.get_activitytypes()?;
+pub fn list_activitytypes(
+    args: ListActivityTypeArgs,
+    ds: &DataStore,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
+    let ats: Vec<_> = skip_corrupt(ds.get_activitytypes()?)?
+        .into_iter()
+        .filter(|at| args.all || !at.is_archived())
+        .collect();
+    if output != OutputFormat::Text {
+        return print_records(&ats, output);
+    }
     for at in ats.iter() {
         if args.verbose {
             print!("{}. ", at.id());
         }
-        println!("{}", at.name());
+        if at.is_archived() {
+            println!("{} (archived)", at.name());
+        } else {
+            println!("{}", at.name());
+        }
     }
 
     if ats.is_empty() {
@@ -336,22 +1048,46 @@ pub fn list_activitytypes(args: ListActivityTypeArgs, ds: &DataStore) -> anyhow:
     Ok(())
 }
 
-pub fn list_counts(args: ListCountArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn list_counts(
+    args: ListCountArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("List counts failed, no such project: {}", args.project);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "List counts failed, no such project: {}",
+            args.project
+        )));
     };
 
-    let repos = ds.get_repos(&project)?;
+    let repos = match &args.repo {
+        Some(path) => match ds.get_repo(path)? {
+            Some(repo) if repo.project() == project.id() => vec![repo],
+            _ => bail!(CliError::NotFound(format!(
+                "List counts failed, no such repository for project {}: {}",
+                project.name(),
+                path.display()
+            ))),
+        },
+        None => skip_corrupt(ds.get_repos(&project)?)?,
+    };
+    if output != OutputFormat::Text {
+        let mut counts = Vec::new();
+        for repo in repos.iter() {
+            counts.extend(skip_corrupt(ds.get_counts(repo)?)?);
+        }
+        return print_records(&counts, output);
+    }
     for repo in repos.iter() {
-        let counts = ds.get_counts(repo)?;
+        let counts = skip_corrupt(ds.get_counts(repo)?)?;
         for count in counts {
             if args.verbose {
                 print!("{}. ", count.id());
             }
             println!(
                 "{} {} has {} lines of code",
-                count.date().format("%A %d %B %Y at %I:%M%P"),
+                ctx.local(count.date()).format("%A %d %B %Y at %I:%M%P"),
                 repo.path().display(),
                 ds.get_total_loc(&count)?
             );
@@ -365,18 +1101,30 @@ pub fn list_counts(args: ListCountArgs, ds: &DataStore) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn list_repos(args: ListRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn list_repos(args: ListRepoArgs, ds: &DataStore, output: OutputFormat) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("List repositories failed, no such project: {}", args.project);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "List repositories failed, no such project: {}",
+            args.project
+        )));
     };
 
-    let repos = ds.get_repos(&project)?;
+    let repos: Vec<_> = skip_corrupt(ds.get_repos(&project)?)?
+        .into_iter()
+        .filter(|r| args.all || !r.is_archived())
+        .collect();
+    if output != OutputFormat::Text {
+        return print_records(&repos, output);
+    }
     for repo in repos.iter() {
         if args.verbose {
             print!("{}. ", repo.id());
         }
-        println!("{}", repo.path().display());
+        if repo.is_archived() {
+            println!("{} (archived)", repo.path().display());
+        } else {
+            println!("{}", repo.path().display());
+        }
     }
 
     if repos.is_empty() {
@@ -388,13 +1136,17 @@ pub fn list_repos(args: ListRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
 
 pub fn rename_project(args: RenameProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(mut project) = ds.get_project(&args.old_name)? else {
-        eprintln!("Rename failed, no such project: {}", args.old_name);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Rename failed, no such project: {}",
+            args.old_name
+        )));
     };
 
     if ds.get_project(&args.new_name)?.is_some() {
-        eprintln!("Rename failed, project already exists: {}", args.new_name);
-        process::exit(1);
+        bail!(CliError::AlreadyExists(format!(
+            "Rename failed, project already exists: {}",
+            args.new_name
+        )));
     }
 
     project.set_name(args.new_name);
@@ -405,16 +1157,17 @@ pub fn rename_project(args: RenameProjectArgs, ds: &DataStore) -> anyhow::Result
 
 pub fn rename_activitytype(args: RenameActivityTypeArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(mut at) = ds.get_activitytype(&args.old_name)? else {
-        eprintln!("Rename failed, no such activity type: {}", args.old_name);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Rename failed, no such activity type: {}",
+            args.old_name
+        )));
     };
 
     if ds.get_activitytype(&args.new_name)?.is_some() {
-        eprintln!(
+        bail!(CliError::AlreadyExists(format!(
             "Rename failed, activity type already exists: {}",
             args.new_name
-        );
-        process::exit(1);
+        )));
     }
 
     at.set_name(args.new_name);
@@ -423,66 +1176,394 @@ pub fn rename_activitytype(args: RenameActivityTypeArgs, ds: &DataStore) -> anyh
     Ok(())
 }
 
-pub fn start_activity(args: StartActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn split_activity(
+    args: SplitActivityArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
+    let Some(activity) = ds.get_activity_with_id(args.id)? else {
+        bail!(CliError::NotFound(format!(
+            "Split failed, no such activity: {}",
+            args.id
+        )));
+    };
+
+    let at = match args.activity_type.as_deref() {
+        Some(name) => ds.get_activitytype(name)?,
+        None => ds.get_activitytype_with_id(activity.atype())?,
+    };
+    let Some(at) = at else {
+        bail!(CliError::NotFound(format!(
+            "Split failed, no such activity type: {}",
+            args.activity_type
+                .unwrap_or_else(|| activity.atype().to_string())
+        )));
+    };
+
+    let description = args
+        .description
+        .or_else(|| activity.description().map(str::to_string));
+    let time = args.time.datetime(ctx)?;
+    let second = ds.split_activity(&activity, time, &at, description)?;
+    println!(
+        "Split activity {}, created activity {}",
+        activity.id(),
+        second.id()
+    );
+
+    Ok(())
+}
+
+pub fn start_activity(
+    args: StartActivityArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("Start activity failed, no such project: {}", args.project);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Start activity failed, no such project: {}",
+            args.project
+        )));
     };
 
     if ds.get_running_activity(&project)?.is_some() {
-        eprintln!(
-            "Start activity failed, an activity is aready running for project: {}",
+        bail!(CliError::Conflict(format!(
+            "Start activity failed, an activity is already running for project: {}",
             project.name()
-        );
-        process::exit(1);
+        )));
     }
 
     let Some(at) = ds.get_activitytype(&args.activity_type)? else {
-        eprintln!(
+        bail!(CliError::NotFound(format!(
             "Start activity failed, no such activity type: {}",
             args.activity_type
-        );
-        process::exit(1);
+        )));
     };
 
-    ds.start_activity(&project, &at, args.description)?;
+    let start = time_or_now(args.at, args.ago, ctx)?;
+    ds.start_activity_at(&project, &at, args.description, &args.tags, start)?;
 
     Ok(())
 }
 
-pub fn stop_activity(args: StopActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn stop_activity(
+    args: StopActivityArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
     let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("Stop activity failed, no such project: {}", args.project);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Stop activity failed, no such project: {}",
+            args.project
+        )));
+    };
+
+    let Some(activity) = ds.get_running_activity(&project)? else {
+        bail!(CliError::NotFound(format!(
+            "Stop activity failed, no activity running for {}",
+            project.name()
+        )));
     };
 
+    let end = time_or_now(args.at, args.ago, ctx)?;
+    if end < activity.start_time() {
+        let local_start = ctx.local(activity.start_time());
+        let local_end = ctx.local(end);
+        bail!(CliError::Invalid(format!(
+            "Stop activity failed, end time {} is before start time {}",
+            local_end.format("%I:%M%P on %A %d %B %Y"),
+            local_start.format("%I:%M%P on %A %d %B %Y")
+        )));
+    }
+
     if ds
-        .stop_running_activity(&project, args.description)?
+        .stop_running_activity_at(&project, args.description, end)?
         .is_none()
     {
-        eprintln!(
+        bail!(CliError::NotFound(format!(
             "Stop activity failed, no activity running for {}",
             project.name()
-        );
-        process::exit(1)
+        )));
     }
 
     if !args.no_count {
-        for repo in ds.get_repos(&project)? {
-            ds.create_count(&repo)?;
+        let repos = skip_corrupt(ds.get_repos(&project)?)?;
+        for repo in repos.iter().filter(|r| !r.is_archived()) {
+            ds.create_count(repo)?;
         }
     }
 
     Ok(())
 }
 
+pub fn merge_activities(args: MergeActivitiesArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let mut activities = Vec::new();
+    for id in args.ids {
+        let Some(activity) = ds.get_activity_with_id(id)? else {
+            bail!(CliError::NotFound(format!(
+                "Merge failed, no such activity: {}",
+                id
+            )));
+        };
+        activities.push(activity);
+    }
+
+    ds.merge_activities(&activities)?;
+
+    Ok(())
+}
+
+pub fn pause_activity(args: PauseActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(project) = ds.get_project(&args.project)? else {
+        bail!(CliError::NotFound(format!(
+            "Pause activity failed, no such project: {}",
+            args.project
+        )));
+    };
+
+    if ds.pause_running_activity(&project)?.is_none() {
+        bail!(CliError::NotFound(format!(
+            "Pause activity failed, no activity running for {}",
+            project.name()
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn resume_activity(args: ResumeActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(project) = ds.get_project(&args.project)? else {
+        bail!(CliError::NotFound(format!(
+            "Resume activity failed, no such project: {}",
+            args.project
+        )));
+    };
+
+    if ds.resume_running_activity(&project)?.is_none() {
+        bail!(CliError::NotFound(format!(
+            "Resume activity failed, no activity running for {}",
+            project.name()
+        )));
+    }
+
+    Ok(())
+}
+
+pub fn resume_last_activity(args: ResumeArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let project = match args.project {
+        Some(name) => {
+            let Some(project) = ds.get_project(&name)? else {
+                bail!(CliError::NotFound(format!(
+                    "Resume failed, no such project: {}",
+                    name
+                )));
+            };
+            Some(project)
+        }
+        None => None,
+    };
+
+    if ds.resume_last_activity(project.as_ref())?.is_none() {
+        bail!(CliError::NotFound(
+            "Resume failed, no finished activity to resume".to_string()
+        ));
+    }
+
+    Ok(())
+}
+
+pub fn switch_activity(args: SwitchArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(project) = ds.get_project(&args.project)? else {
+        bail!(CliError::NotFound(format!(
+            "Switch failed, no such project: {}",
+            args.project
+        )));
+    };
+
+    let Some(at) = ds.get_activitytype(&args.activity_type)? else {
+        bail!(CliError::NotFound(format!(
+            "Switch failed, no such activity type: {}",
+            args.activity_type
+        )));
+    };
+
+    ds.switch_activity(&project, &at, args.description)?;
+
+    Ok(())
+}
+
+pub fn tag_activity(args: TagActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(activity) = ds.get_activity_with_id(args.id)? else {
+        bail!(CliError::NotFound(format!(
+            "Tag failed, no such activity: {}",
+            args.id
+        )));
+    };
+
+    ds.tag_activity(&activity, &args.tags)?;
+
+    Ok(())
+}
+
+pub fn tag_project(args: TagProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Tag failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    ds.tag_project(&project, &args.tags)?;
+
+    Ok(())
+}
+
+pub fn untag_activity(args: TagActivityArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(activity) = ds.get_activity_with_id(args.id)? else {
+        bail!(CliError::NotFound(format!(
+            "Untag failed, no such activity: {}",
+            args.id
+        )));
+    };
+
+    ds.untag_activity(&activity, &args.tags)?;
+
+    Ok(())
+}
+
+pub fn untag_project(args: TagProjectArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Untag failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    ds.untag_project(&project, &args.tags)?;
+
+    Ok(())
+}
+
+pub fn update_project_description(
+    args: UpdateProjectDescriptionArgs,
+    ds: &DataStore,
+) -> anyhow::Result<()> {
+    let Some(mut project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    project.set_description(args.description);
+    ds.update_project(&project)?;
+
+    Ok(())
+}
+
+pub fn update_project_status(args: UpdateProjectStatusArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(mut project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    project.set_status(args.status);
+    ds.update_project(&project)?;
+
+    Ok(())
+}
+
+pub fn update_project_created(
+    args: UpdateProjectCreatedArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
+    let Some(mut project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    project.set_created(args.created.datetime(ctx)?);
+    ds.update_project(&project)?;
+
+    Ok(())
+}
+
+pub fn update_project_target(
+    args: UpdateProjectTargetArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
+    let Some(mut project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    let target = match args.target {
+        Some(target) => Some(ctx.local(target.datetime(ctx)?).date_naive()),
+        None => None,
+    };
+    project.set_target(target);
+    ds.update_project(&project)?;
+
+    Ok(())
+}
+
+pub fn update_project_url(args: UpdateProjectUrlArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(mut project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    project.set_url(args.url);
+    ds.update_project(&project)?;
+
+    Ok(())
+}
+
+pub fn update_project_parent(args: UpdateProjectParentArgs, ds: &DataStore) -> anyhow::Result<()> {
+    let Some(mut project) = ds.get_project(&args.name)? else {
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such project: {}",
+            args.name
+        )));
+    };
+
+    let parent = match args.parent {
+        Some(parent) => {
+            let Some(parent) = ds.get_project(&parent)? else {
+                bail!(CliError::NotFound(format!(
+                    "Update failed, no such parent project: {}",
+                    parent
+                )));
+            };
+            Some(parent)
+        }
+        None => None,
+    };
+    project.set_parent(parent.as_ref());
+    ds.update_project(&project)?;
+
+    Ok(())
+}
+
 pub fn update_activity_description(
     args: UpdateActivityDescriptionArgs,
     ds: &DataStore,
 ) -> anyhow::Result<()> {
     let Some(mut activity) = ds.get_activity_with_id(args.id)? else {
-        eprintln!("Update failed, no such actvity: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such activity: {}",
+            args.id
+        )));
     };
 
     activity.set_description(args.description);
@@ -496,13 +1577,17 @@ pub fn update_activity_atype(
     ds: &DataStore,
 ) -> anyhow::Result<()> {
     let Some(mut activity) = ds.get_activity_with_id(args.id)? else {
-        eprintln!("Update failed, no such actvity: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such activity: {}",
+            args.id
+        )));
     };
 
     let Some(at) = ds.get_activitytype(&args.atype)? else {
-        eprintln!("Update failed, no such activity type: {}", args.atype);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such activity type: {}",
+            args.atype
+        )));
     };
 
     activity.set_atype(at.id());
@@ -511,23 +1596,60 @@ pub fn update_activity_atype(
     Ok(())
 }
 
-pub fn update_activity_end(args: UpdateActivityEndArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn update_activity_start(
+    args: UpdateActivityStartArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
+    let Some(mut activity) = ds.get_activity_with_id(args.id)? else {
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such activity: {}",
+            args.id
+        )));
+    };
+
+    let start = args.start.datetime(ctx)?;
+    if let Some(end) = activity.end_time() {
+        if start > end {
+            let local_start = ctx.local(start);
+            let local_end = ctx.local(end);
+            bail!(CliError::Invalid(format!(
+                "Update failed, start time {} is after end time {}",
+                local_start.format("%I:%M%P on %A %d %B %Y"),
+                local_end.format("%I:%M%P on %A %d %B %Y")
+            )));
+        }
+    }
+
+    activity.set_start_time(start);
+    ds.update_activity(&activity)?;
+
+    Ok(())
+}
+
+pub fn update_activity_end(
+    args: UpdateActivityEndArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
     let Some(mut activity) = ds.get_activity_with_id(args.id)? else {
-        eprintln!("Update failed, no such actvity: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such activity: {}",
+            args.id
+        )));
     };
 
-    if args.end < activity.start_time() {
-        let local_start: DateTime<Local> = DateTime::from(activity.start_time());
-        eprintln!(
+    let end = args.end.datetime(ctx)?;
+    if end < activity.start_time() {
+        let local_start = ctx.local(activity.start_time());
+        bail!(CliError::Invalid(format!(
             "Update failed, end time {} is before start time {}",
-            args.end.format("%I:%M%P on %A %d %B %Y"),
+            ctx.local(end).format("%I:%M%P on %A %d %B %Y"),
             local_start.format("%I:%M%P on %A %d %B %Y")
-        );
-        process::exit(1);
+        )));
     }
 
-    activity.set_end_time(Some(args.end));
+    activity.set_end_time(Some(end));
     ds.update_activity(&activity)?;
 
     Ok(())
@@ -538,13 +1660,17 @@ pub fn update_activity_project(
     ds: &DataStore,
 ) -> anyhow::Result<()> {
     let Some(mut activity) = ds.get_activity_with_id(args.id)? else {
-        eprintln!("Update failed, no such actvity: {}", args.id);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such activity: {}",
+            args.id
+        )));
     };
 
     let Some(project) = ds.get_project(&args.project)? else {
-        eprintln!("Update failed, no such project: {}", args.project);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such project: {}",
+            args.project
+        )));
     };
 
     activity.set_project(project.id());
@@ -555,8 +1681,10 @@ pub fn update_activity_project(
 
 pub fn update_activitytype(args: UpdateActivityTypeArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(mut at) = ds.get_activitytype(&args.name)? else {
-        eprintln!("Update failed, no such activity type: {}", args.name);
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such activity type: {}",
+            args.name
+        )));
     };
 
     at.set_description(args.description);
@@ -567,16 +1695,17 @@ pub fn update_activitytype(args: UpdateActivityTypeArgs, ds: &DataStore) -> anyh
 
 pub fn update_repo(args: UpdateRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
     let Some(mut repo) = ds.get_repo(&args.old_path)? else {
-        eprintln!("Update failed, no such repository with path {}", args.old_path.display());
-        process::exit(1);
+        bail!(CliError::NotFound(format!(
+            "Update failed, no such repository with path: {}",
+            args.old_path.display()
+        )));
     };
 
     if ds.get_repo(&args.new_path)?.is_some() {
-        eprintln!(
+        bail!(CliError::AlreadyExists(format!(
             "Update failed, repository with path already exists: {}",
             args.new_path.display()
-        );
-        process::exit(1);
+        )));
     }
 
     repo.set_path(args.new_path);
@@ -585,44 +1714,54 @@ pub fn update_repo(args: UpdateRepoArgs, ds: &DataStore) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn generate_report(args: GenerateArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn generate_report(
+    args: GenerateArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<()> {
+    let (start, end) = report_range(&args, ctx)?;
+
     if args.name == "all" {
-        let projects = ds.get_projects()?;
+        let projects = skip_corrupt(ds.get_projects()?)?;
 
         for project in projects {
-            let report = ds.create_report(&project, args.start, args.end)?;
-            report.print();
+            let report = ds.create_report(&project, start, end, args.tag.as_deref())?;
+            report.print(ctx.tz());
         }
     } else {
         let Some(project) = ds.get_project(&args.name)? else {
-            eprintln!("Generate report failed, no such project: {}", args.name);
-            process::exit(1);
+            bail!(CliError::NotFound(format!(
+                "Generate report failed, no such project: {}",
+                args.name
+            )));
         };
 
-        let report = ds.create_report(&project, args.start, args.end)?;
-        report.print();
+        let report = ds.create_report(&project, start, end, args.tag.as_deref())?;
+        report.print(ctx.tz());
     }
 
     Ok(())
 }
 
-pub fn generate_json(args: GenerateArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn generate_json(args: GenerateArgs, ds: &DataStore, ctx: &TimeContext) -> anyhow::Result<()> {
+    let (start, end) = report_range(&args, ctx)?;
+
     if args.name == "all" {
-        let reports: Vec<_> = ds
-            .get_projects()?
+        let reports = skip_corrupt(ds.get_projects()?)?
             .iter()
-            .map(|p| ds.create_report(p, args.start, args.end).ok())
-            .filter_map(|r| r)
-            .collect();
+            .map(|p| ds.create_report(p, start, end, args.tag.as_deref()))
+            .collect::<Result<Vec<_>, _>>()?;
         let json = serde_json::to_string_pretty(&reports)?;
         print!("{}", json);
     } else {
         let Some(project) = ds.get_project(&args.name)? else {
-            eprintln!("Generate report failed, no such project: {}", args.name);
-            process::exit(1);
+            bail!(CliError::NotFound(format!(
+                "Generate report failed, no such project: {}",
+                args.name
+            )));
         };
 
-        let report = ds.create_report(&project, args.start, args.end)?;
+        let report = ds.create_report(&project, start, end, args.tag.as_deref())?;
         let json = serde_json::to_string(&report)?;
         print!("{}", json);
     }
@@ -630,37 +1769,52 @@ pub fn generate_json(args: GenerateArgs, ds: &DataStore) -> anyhow::Result<()> {
     Ok(())
 }
 
-pub fn show_status(args: StatusArgs, ds: &DataStore) -> anyhow::Result<()> {
+pub fn set_running_policy(args: SetRunningPolicyArgs, ds: &DataStore) -> anyhow::Result<()> {
+    ds.set_running_policy(args.policy)?;
+
+    Ok(())
+}
+
+pub fn show_status(
+    args: StatusArgs,
+    ds: &DataStore,
+    ctx: &TimeContext,
+    output: OutputFormat,
+) -> anyhow::Result<()> {
     if let Some(name) = args.name {
         let Some(project) = ds.get_project(&name)? else {
-            eprintln!("Show status failed, no such project: {}", name);
-            process::exit(1);
+            bail!(CliError::NotFound(format!(
+                "Show status failed, no such project: {}",
+                name
+            )));
         };
 
+        if output != OutputFormat::Text {
+            let running: Vec<_> = ds.get_running_activity(&project)?.into_iter().collect();
+            return print_records(&running, output);
+        }
+
         if let Some(activity) = ds.get_running_activity(&project)? {
             let atype = match ds.get_activitytype_with_id(activity.atype())? {
                 Some(at) => at.name().to_string(),
                 None => "Unknown".to_string(),
             };
-            let local_start: DateTime<Local> = DateTime::from(activity.start_time());
-            let duration = Utc::now() - activity.start_time();
+            let local_start = ctx.local(activity.start_time());
+            let duration = activity.worked_until(Utc::now());
             println!(
-                "{} started at {} and has been running for {} minutes.",
+                "{} started at {} and has been running for {} minutes{}.",
                 atype,
                 local_start.format("%I:%M%P on %A %d %B %Y"),
-                duration.num_minutes()
+                duration.num_minutes(),
+                paused_phrase(&activity, ctx)
             );
         } else {
             println!("No activities running for {}.", name);
         }
     } else {
-        let projects = ds.get_projects()?;
-        let mut activities = Vec::new();
-
-        for project in projects {
-            if let Some(activity) = ds.get_running_activity(&project)? {
-                activities.push(activity);
-            }
+        let activities = ds.get_running_activities()?;
+        if output != OutputFormat::Text {
+            return print_records(&activities, output);
         }
 
         if activities.is_empty() {
@@ -668,6 +1822,13 @@ pub fn show_status(args: StatusArgs, ds: &DataStore) -> anyhow::Result<()> {
             return Ok(());
         }
 
+        if activities.len() > 1 && ds.running_policy()? == RunningPolicy::PerProject {
+            println!(
+                "Warning: {} activities are running at the same time in different projects.",
+                activities.len()
+            );
+        }
+
         for activity in activities {
             let atype = match ds.get_activitytype_with_id(activity.atype())? {
                 Some(at) => at.name().to_string(),
@@ -678,17 +1839,152 @@ pub fn show_status(args: StatusArgs, ds: &DataStore) -> anyhow::Result<()> {
                 Some(project) => project.name().to_string(),
                 None => "Unknown".to_string(),
             };
-            let local_start: DateTime<Local> = DateTime::from(activity.start_time());
-            let duration = Utc::now() - activity.start_time();
+            let local_start = ctx.local(activity.start_time());
+            let duration = activity.worked_until(Utc::now());
             println!(
-                "Project {}: {} started at {} and has been running for {} minutes.",
+                "Project {}: {} started at {} and has been running for {} minutes{}.",
                 project,
                 atype,
                 local_start.format("%I:%M%P on %A %d %B %Y"),
-                duration.num_minutes()
+                duration.num_minutes(),
+                paused_phrase(&activity, ctx)
             );
         }
     }
 
     Ok(())
 }
+
+fn describe_overlap(
+    overlap: &Overlap,
+    ds: &DataStore,
+    ctx: &TimeContext,
+) -> anyhow::Result<String> {
+    let mut spans = Vec::new();
+    for activity in [overlap.first(), overlap.second()] {
+        let project = match ds.get_project_with_id(activity.project())? {
+            Some(project) => project.name().to_string(),
+            None => "Unknown".to_string(),
+        };
+        let atype = match ds.get_activitytype_with_id(activity.atype())? {
+            Some(at) => at.name().to_string(),
+            None => "Unknown".to_string(),
+        };
+        let start = ctx
+            .local(activity.start_time())
+            .format("%I:%M%P on %A %d %B %Y");
+        let span = match activity.end_time() {
+            Some(end) => format!(
+                "{}. {} {} from {} until {}",
+                activity.id(),
+                project,
+                atype,
+                start,
+                ctx.local(end).format("%I:%M%P on %A %d %B %Y")
+            ),
+            None => format!(
+                "{}. {} {} started at {}, and is still running",
+                activity.id(),
+                project,
+                atype,
+                start
+            ),
+        };
+        spans.push(span);
+    }
+
+    Ok(format!(
+        "{}\n  overlaps {}\n  for {} minutes",
+        spans[0],
+        spans[1],
+        overlap.duration().num_minutes()
+    ))
+}
+
+// Rows that cannot be read are reported and left out, so that one damaged row
+// does not hide the rest.  Any other error is returned.
+fn skip_corrupt<T>(rows: impl IntoIterator<Item = Result<T, Error>>) -> anyhow::Result<Vec<T>> {
+    let mut read = Vec::new();
+    for row in rows {
+        match row {
+            Ok(row) => read.push(row),
+            Err(e @ Error::CorruptRow { .. }) => eprintln!("Skipping damaged row, {}", e),
+            Err(e) => return Err(e.into()),
+        }
+    }
+
+    Ok(read)
+}
+
+fn total_worked(activities: &[Activity]) -> Duration {
+    let now = Utc::now();
+    activities
+        .iter()
+        .map(|a| a.worked_until(a.end_time().unwrap_or(now)))
+        .fold(Duration::zero(), |total, worked| total + worked)
+}
+
+fn duration_to_str(duration: Duration) -> String {
+    let hours = match duration.num_hours() {
+        0 => String::new(),
+        1 => "1 hour".to_string(),
+        h => format!("{} hours", h),
+    };
+
+    let minutes = match duration.num_minutes() % 60 {
+        0 => String::new(),
+        1 => "1 minute".to_string(),
+        m => format!("{} minutes", m),
+    };
+
+    match (hours.is_empty(), minutes.is_empty()) {
+        (false, false) => format!("{} {}", hours, minutes),
+        (true, false) => minutes,
+        (false, true) => hours,
+        (true, true) => "less than a minutes".to_string(),
+    }
+}
+
+// A duration in hours and minutes for a table cell, for example '2h 05m'.
+fn duration_to_short_str(duration: Duration) -> String {
+    format!(
+        "{}h {:02}m",
+        duration.num_hours(),
+        duration.num_minutes() % 60
+    )
+}
+
+fn paused_phrase(activity: &Activity, ctx: &TimeContext) -> String {
+    match activity.paused_since() {
+        Some(since) => format!(
+            ", paused since {}",
+            ctx.local(since).format("%I:%M%P on %A %d %B %Y")
+        ),
+        None => String::new(),
+    }
+}
+
+fn time_or_now(
+    at: Option<TimeArg>,
+    ago: Option<Duration>,
+    ctx: &TimeContext,
+) -> anyhow::Result<DateTime<Utc>> {
+    Ok(match (at, ago) {
+        (Some(at), _) => at.datetime(ctx)?,
+        (None, Some(ago)) => Utc::now() - ago,
+        (None, None) => Utc::now(),
+    })
+}
+
+type ReportRange = (Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+fn report_range(args: &GenerateArgs, ctx: &TimeContext) -> anyhow::Result<ReportRange> {
+    if let Some(period) = &args.period {
+        let period = period.range(ctx)?;
+        return Ok((Some(period.start), Some(period.end)));
+    }
+
+    let start = args.start.as_ref().map(|s| s.datetime(ctx)).transpose()?;
+    let end = args.end.as_ref().map(|e| e.end_date(ctx)).transpose()?;
+    Ok((start, end))
+}
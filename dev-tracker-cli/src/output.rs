@@ -0,0 +1,40 @@
+use std::io;
+
+use serde::Serialize;
+
+use crate::cli::OutputFormat;
+
+/// Writes `records` to standard output as a JSON array, or as CSV or TSV with
+/// a header row.  Text output is written by each command itself.
+pub fn print_records<T: Serialize>(records: &[T], format: OutputFormat) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Text => unreachable!("text output is written by each command"),
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(records)?),
+        OutputFormat::Csv => write_delimited(records, b',')?,
+        OutputFormat::Tsv => write_delimited(records, b'\t')?,
+    }
+
+    Ok(())
+}
+
+/// Writes a single record, as a JSON object rather than an array.
+pub fn print_record<T: Serialize>(record: &T, format: OutputFormat) -> anyhow::Result<()> {
+    match format {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(record)?),
+        _ => print_records(std::slice::from_ref(record), format)?,
+    }
+
+    Ok(())
+}
+
+fn write_delimited<T: Serialize>(records: &[T], delimiter: u8) -> anyhow::Result<()> {
+    let mut writer = csv::WriterBuilder::new()
+        .delimiter(delimiter)
+        .from_writer(io::stdout());
+    for record in records {
+        writer.serialize(record)?;
+    }
+    writer.flush()?;
+
+    Ok(())
+}
@@ -0,0 +1,227 @@
+use std::io::{self, IsTerminal};
+
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+use crate::cli::ColorChoice;
+
+// Columns that can be cut to fit the terminal are never made narrower than
+// this, or than their header.
+const MIN_WIDTH: usize = 8;
+const GAP: &str = "  ";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Style {
+    #[default]
+    Plain,
+    Bold,
+    Green,
+    Yellow,
+}
+
+impl Style {
+    fn code(&self) -> &'static str {
+        match self {
+            Style::Plain => "",
+            Style::Bold => "\x1b[1m",
+            Style::Green => "\x1b[32m",
+            Style::Yellow => "\x1b[33m",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Cell {
+    text: String,
+    style: Style,
+}
+
+impl Cell {
+    pub fn styled(text: impl Into<String>, style: Style) -> Self {
+        Self {
+            text: text.into(),
+            style,
+        }
+    }
+}
+
+impl From<String> for Cell {
+    fn from(text: String) -> Self {
+        Cell::styled(text, Style::Plain)
+    }
+}
+
+impl From<&str> for Cell {
+    fn from(text: &str) -> Self {
+        Cell::styled(text, Style::Plain)
+    }
+}
+
+struct Column {
+    header: &'static str,
+    align: Align,
+    truncate: bool,
+}
+
+/// Where a table is written: whether to use color, and the width to fit it
+/// in.  Output that is not going to a terminal is never colored or cut.
+#[derive(Clone, Copy, Debug)]
+pub struct Terminal {
+    color: bool,
+    width: Option<usize>,
+}
+
+impl Terminal {
+    /// Colors are used when `choice` is 'always', or when it is 'auto',
+    /// standard output is a terminal, and `NO_COLOR` is not set.
+    pub fn detect(choice: ColorChoice) -> Self {
+        let tty = io::stdout().is_terminal();
+        let no_color = std::env::var_os("NO_COLOR").is_some_and(|v| !v.is_empty());
+        let color = match choice {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => tty && !no_color,
+        };
+        let width = tty
+            .then(terminal_size::terminal_size)
+            .flatten()
+            .map(|(width, _)| width.0 as usize);
+
+        Self { color, width }
+    }
+}
+
+/// Rows of text written with their columns aligned, and an optional footer
+/// for totals.
+#[derive(Default)]
+pub struct Table {
+    columns: Vec<Column>,
+    rows: Vec<Vec<Cell>>,
+    footer: Option<Vec<Cell>>,
+}
+
+impl Table {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a column, if `truncate` is true its cells are cut short when the
+    /// table is too wide for the terminal.
+    pub fn column(mut self, header: &'static str, align: Align, truncate: bool) -> Self {
+        self.columns.push(Column {
+            header,
+            align,
+            truncate,
+        });
+        self
+    }
+
+    pub fn row(&mut self, cells: Vec<Cell>) {
+        self.rows.push(cells);
+    }
+
+    pub fn footer(&mut self, cells: Vec<Cell>) {
+        self.footer = Some(cells);
+    }
+
+    pub fn print(&self, terminal: &Terminal) {
+        let widths = self.widths(terminal.width);
+
+        let headers: Vec<_> = self
+            .columns
+            .iter()
+            .map(|c| Cell::styled(c.header, Style::Bold))
+            .collect();
+        println!("{}", self.line(&headers, &widths, terminal.color));
+        for row in &self.rows {
+            println!("{}", self.line(row, &widths, terminal.color));
+        }
+        if let Some(footer) = &self.footer {
+            let footer: Vec<_> = footer
+                .iter()
+                .map(|c| Cell::styled(c.text.clone(), Style::Bold))
+                .collect();
+            println!("{}", self.line(&footer, &widths, terminal.color));
+        }
+    }
+
+    // The width of each column, narrowing the widest columns that can be cut
+    // until the table fits in `limit`.
+    fn widths(&self, limit: Option<usize>) -> Vec<usize> {
+        let mut widths: Vec<_> = self.columns.iter().map(|c| c.header.width()).collect();
+        for row in self.rows.iter().chain(self.footer.iter()) {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.text.width());
+            }
+        }
+
+        let Some(limit) = limit else {
+            return widths;
+        };
+        let gaps = GAP.len() * self.columns.len().saturating_sub(1);
+        loop {
+            let total = widths.iter().sum::<usize>() + gaps;
+            if total <= limit {
+                break;
+            }
+            let widest = self
+                .columns
+                .iter()
+                .enumerate()
+                .filter(|(i, c)| c.truncate && widths[*i] > MIN_WIDTH.max(c.header.width()))
+                .max_by_key(|(i, _)| widths[*i]);
+            let Some((i, column)) = widest else {
+                break;
+            };
+            let min = MIN_WIDTH.max(column.header.width());
+            widths[i] -= (total - limit).min(widths[i] - min);
+        }
+
+        widths
+    }
+
+    fn line(&self, cells: &[Cell], widths: &[usize], color: bool) -> String {
+        let mut parts = Vec::new();
+        for (i, (column, width)) in self.columns.iter().zip(widths).enumerate() {
+            let cell = cells.get(i).cloned().unwrap_or_default();
+            let text = fit(&cell.text, *width);
+            let padding = " ".repeat(width - text.width());
+            let text = if color && cell.style != Style::Plain && !text.is_empty() {
+                format!("{}{}\x1b[0m", cell.style.code(), text)
+            } else {
+                text
+            };
+            match column.align {
+                Align::Left => parts.push(format!("{}{}", text, padding)),
+                Align::Right => parts.push(format!("{}{}", padding, text)),
+            }
+        }
+
+        parts.join(GAP).trim_end().to_string()
+    }
+}
+
+// Cuts `text` to `width` columns, ending it with an ellipsis if it was cut.
+fn fit(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+
+    let mut fitted = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        let w = c.width().unwrap_or(0);
+        if used + w + 1 > width {
+            break;
+        }
+        fitted.push(c);
+        used += w;
+    }
+    fitted.push('…');
+    fitted
+}
@@ -0,0 +1,74 @@
+//! Stopping an activity from the command line.
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+struct Dir(PathBuf);
+
+impl Dir {
+    fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("dt-cli-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        Dir(dir)
+    }
+
+    fn subdir(&self, name: &str) -> PathBuf {
+        let path = self.0.join(name);
+        std::fs::create_dir(&path).unwrap();
+        path
+    }
+}
+
+impl Drop for Dir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+fn dt(data: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_dt"))
+        .arg("--data-file")
+        .arg(data)
+        .args(["--tz", "UTC", "--color", "never"])
+        .args(args)
+        .output()
+        .unwrap()
+}
+
+fn ok(data: &Path, args: &[&str]) -> String {
+    let output = dt(data, args);
+    assert!(
+        output.status.success(),
+        "dt {:?} failed: {}",
+        args,
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn stop_counts_only_repos_that_are_not_archived() {
+    let dir = Dir::new("stop");
+    let data = dir.0.join("data.sqlite");
+    let kept = dir.subdir("kept");
+    let archived = dir.subdir("archived");
+
+    ok(&data, &["add", "project", "p", kept.to_str().unwrap()]);
+    ok(&data, &["add", "repo", "p", archived.to_str().unwrap()]);
+    ok(&data, &["archive", "repo", archived.to_str().unwrap()]);
+    ok(&data, &["start", "activity", "p", "Unknown", "--ago", "1h"]);
+    ok(&data, &["stop", "activity", "p"]);
+
+    let repos: serde_json::Value =
+        serde_json::from_str(&ok(&data, &["list", "repos", "p", "--output", "json"])).unwrap();
+    let repos = repos.as_array().unwrap();
+    assert_eq!(repos.len(), 1);
+    assert_eq!(repos[0]["path"], kept.to_str().unwrap());
+
+    let counts: serde_json::Value =
+        serde_json::from_str(&ok(&data, &["list", "counts", "p", "--output", "json"])).unwrap();
+    let counts = counts.as_array().unwrap();
+    assert_eq!(counts.len(), 1);
+    assert_eq!(counts[0]["repo"], repos[0]["id"]);
+}
@@ -1,29 +1,45 @@
-use std::cmp::Ordering;
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
-use chrono::{DateTime, Utc};
-use rusqlite::Connection;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::backup::Progress;
+use rusqlite::{Connection, DatabaseName, OpenFlags};
 use tokei::{Config, Languages};
 
+use crate::history::{self, Operation};
+use crate::integrity::Issue;
+use crate::migrations;
 use crate::model::activity::Activity;
 use crate::model::activitytype::ActivityType;
 use crate::model::count::Count;
-use crate::model::project::Project;
+use crate::model::pause::Pause;
+use crate::model::project::{Project, ProjectStatus};
 use crate::model::repo::Repo;
-use crate::model::{activity, activitytype, count, project, repo};
+use crate::model::tag::Tag;
+use crate::overlap::{Overlap, OverlapResolution};
+use crate::query::{ActivityOrder, ActivityQuery};
 use crate::report::{self, Report};
+use crate::settings::{self, RunningPolicy};
 use crate::Error;
 
+// The number of automatic backups kept for each data file.
+const BACKUPS_KEPT: usize = 5;
+
 #[derive(Debug)]
 pub struct DataStore {
     conn: Connection,
+    path: Option<PathBuf>,
 }
 
 impl DataStore {
     pub fn new(file: Option<&Path>) -> Result<Self, Error> {
         let ds = DataStore::open(file)?;
-        ds.init_tables()?;
+        if ds.needs_migration()? {
+            ds.create_rotating_backup("migration")?;
+        }
+        migrations::migrate(&ds.conn)?;
+        Operation::begin(None, &ds.conn)?;
 
         Ok(ds)
     }
@@ -33,20 +49,180 @@ impl DataStore {
             Some(file) => Connection::open(file)?,
             None => Connection::open_in_memory()?,
         };
+        conn.pragma_update(None, "foreign_keys", true)?;
 
-        let ds = Self { conn };
+        let ds = Self {
+            conn,
+            path: file.map(Path::to_path_buf),
+        };
         Ok(ds)
     }
 
-    fn init_tables(&self) -> Result<(), Error> {
-        project::init_table(&self.conn)?;
-        activity::init_table(&self.conn)?;
-        activitytype::init_table(&self.conn)?;
-        repo::init_table(&self.conn)?;
-        count::init_table(&self.conn)?;
+    pub fn schema_version(&self) -> Result<u32, Error> {
+        migrations::schema_version(&self.conn)
+    }
+
+    // An empty database is created from scratch rather than migrated.
+    fn needs_migration(&self) -> Result<bool, Error> {
+        let tables: u32 = self.conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table'",
+            [],
+            |row| row.get(0),
+        )?;
+        Ok(tables > 0 && self.schema_version()? < migrations::SCHEMA_VERSION)
+    }
+}
+
+// Database
+impl DataStore {
+    /// Copies the database to `file` using SQLite's online backup, replacing
+    /// anything already in `file`.
+    pub fn backup(&self, file: &Path) -> Result<(), Error> {
+        self.conn.backup(DatabaseName::Main, file, None)?;
+        Ok(())
+    }
+
+    /// Replaces the database with the backup in `file`, which is migrated if
+    /// it was made by an older version.  An automatic backup of the current
+    /// database is made first.
+    pub fn restore(&mut self, file: &Path) -> Result<(), Error> {
+        if !file.is_file() {
+            return Err(Error::InvalidBackup(file.display().to_string()));
+        }
+
+        let src = Connection::open_with_flags(file, OpenFlags::SQLITE_OPEN_READ_ONLY)?;
+        let version = migrations::schema_version(&src)
+            .map_err(|_| Error::InvalidBackup(file.display().to_string()))?;
+        if version > migrations::SCHEMA_VERSION {
+            return Err(Error::SchemaTooNew(version));
+        }
+        let projects: u32 = src
+            .query_row(
+                "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = 'projects'",
+                [],
+                |row| row.get(0),
+            )
+            .map_err(|_| Error::InvalidBackup(file.display().to_string()))?;
+        if projects == 0 {
+            return Err(Error::InvalidBackup(file.display().to_string()));
+        }
+        drop(src);
+
+        self.create_rotating_backup("restore")?;
+        // The triggers are made again for the restored tables once it has
+        // been migrated, and must not record the migration.
+        history::drop_triggers(&self.conn)?;
+        self.conn
+            .restore(DatabaseName::Main, file, None::<fn(Progress)>)?;
+        migrations::migrate(&self.conn)?;
+        Operation::begin(None, &self.conn)?;
+
+        Ok(())
+    }
+
+    /// Checks the database file and its rows for problems, such as rows that
+    /// refer to missing rows or activities that end before they start.
+    pub fn check_integrity(&self) -> Result<Vec<Issue>, Error> {
+        Issue::find_all(&self.conn)
+    }
+
+    /// Repairs `issue`, see `IssueKind` for how each kind of issue is fixed.
+    /// Returns an error if the database file itself is damaged.
+    pub fn fix_issue(&self, issue: &Issue) -> Result<(), Error> {
+        let tx = self.conn.unchecked_transaction()?;
+        issue.fix(&tx)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Rebuilds the database file to reclaim the space left by deleted rows.
+    pub fn vacuum(&self) -> Result<(), Error> {
+        self.conn.execute_batch("VACUUM")?;
+        Ok(())
+    }
+
+    /// Backs up the database into a 'backups' directory next to the data
+    /// file, before a migration or a change that cannot easily be reverted.
+    /// Only the newest few automatic backups are kept.  Returns the path of
+    /// the backup, or `None` for an in-memory database.
+    pub fn create_rotating_backup(&self, reason: &str) -> Result<Option<PathBuf>, Error> {
+        let Some(path) = &self.path else {
+            return Ok(None);
+        };
+        let dir = path
+            .parent()
+            .unwrap_or_else(|| Path::new("."))
+            .join("backups");
+        std::fs::create_dir_all(&dir)
+            .map_err(|e| Error::BackupFailed(format!("{}: {}", dir.display(), e)))?;
+
+        let stem = path
+            .file_stem()
+            .map(|s| s.to_string_lossy().to_string())
+            .unwrap_or_else(|| "dev-tracker".to_string());
+        let file = dir.join(format!(
+            "{}-{}-{}.sqlite",
+            stem,
+            Utc::now().format("%Y%m%d-%H%M%S%3f"),
+            reason
+        ));
+        self.backup(&file)?;
+
+        // The timestamp in the name sorts the backups oldest first.
+        let mut backups: Vec<PathBuf> = std::fs::read_dir(&dir)
+            .map_err(|e| Error::BackupFailed(format!("{}: {}", dir.display(), e)))?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|p| {
+                p.file_name()
+                    .map(|n| n.to_string_lossy())
+                    .is_some_and(|n| n.starts_with(&format!("{}-", stem)) && n.ends_with(".sqlite"))
+            })
+            .collect();
+        backups.sort();
+        if backups.len() > BACKUPS_KEPT {
+            for old in &backups[..backups.len() - BACKUPS_KEPT] {
+                let _ = std::fs::remove_file(old);
+            }
+        }
 
+        Ok(Some(file))
+    }
+}
+
+// History
+impl DataStore {
+    /// Starts a new operation with `description`, later changes are grouped
+    /// under it in the history and are undone together.  An operation is
+    /// started automatically when the data store is created.
+    pub fn begin_operation(&self, description: &str) -> Result<(), Error> {
+        Operation::begin(Some(description), &self.conn)?;
         Ok(())
     }
+
+    /// The `limit` most recent operations that made changes, newest first,
+    /// including those that have been undone.
+    pub fn get_history(&self, limit: u64) -> Result<Vec<Operation>, Error> {
+        Operation::get_latest(limit, true, &self.conn)
+    }
+
+    /// Reverts the `count` most recent operations that have not already been
+    /// undone, newest first.  Either every operation is undone or none are.
+    /// Returns the operations that were undone.
+    pub fn undo(&self, count: u64) -> Result<Vec<Operation>, Error> {
+        let tx = self.conn.unchecked_transaction()?;
+        // Rows are restored one at a time, so a parent row may only be back
+        // in place after its children are.
+        tx.pragma_update(None, "defer_foreign_keys", true)?;
+
+        let operations = Operation::get_latest(count, false, &tx)?;
+        for operation in &operations {
+            operation.undo(&tx)?;
+        }
+        tx.commit()?;
+
+        Ok(operations)
+    }
 }
 
 // Project
@@ -62,38 +238,93 @@ impl DataStore {
         Ok(project)
     }
 
-    pub fn delete_project(&self, project: Project) -> Result<(), Error> {
-        let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
+    /// Archives `project`, which hides it from listings and stops new
+    /// activities being recorded for it but keeps it in reports.
+    pub fn archive_project(&self, project: &Project) -> Result<(), Error> {
+        self.set_project_status(project, |_| ProjectStatus::Archived)
+    }
+
+    /// Restores `project` to the status it had before it was archived, or to
+    /// active if that is not known.  Projects that are not archived are left
+    /// as they are.
+    pub fn unarchive_project(&self, project: &Project) -> Result<(), Error> {
+        self.set_project_status(project, |p| match p.status {
+            ProjectStatus::Archived => p.archived_status.unwrap_or_default(),
+            status => status,
+        })
+    }
+
+    fn set_project_status(
+        &self,
+        project: &Project,
+        status: impl FnOnce(&Project) -> ProjectStatus,
+    ) -> Result<(), Error> {
+        let Some(mut project) = Project::get_with_id(project.id, &self.conn)? else {
             return Err(Error::ProjectNotFound(project.id.to_string()));
         };
 
-        let activities: Vec<_> = Activity::get_all(&self.conn)?
-            .into_iter()
-            .filter(|a| a.project == project.id)
-            .collect();
+        project.set_status(status(&project));
+        project.update(&self.conn)?;
 
-        for activity in activities {
-            self.delete_activity(activity)?;
-        }
+        Ok(())
+    }
 
-        let repos: Vec<_> = Repo::get_all(&self.conn)?
-            .into_iter()
-            .filter(|r| r.project == project.id)
-            .collect();
+    /// Creates a project with `name` as a sub-project of `parent`.
+    pub fn create_child_project(&self, name: &str, parent: &Project) -> Result<Project, Error> {
+        if !Project::get_with_name(name, &self.conn)?.is_empty() {
+            return Err(Error::ProjectAlreadyExists(name.to_string()));
+        }
 
-        for repo in repos {
-            self.delete_repo(repo)?;
+        if Project::get_with_id(parent.id, &self.conn)?.is_none() {
+            return Err(Error::ProjectNotFound(parent.id.to_string()));
         }
 
+        let mut project = Project::new(name.to_string());
+        project.set_parent(Some(parent));
+        project.create(&self.conn)?;
+
+        Ok(project)
+    }
+
+    pub fn delete_project(&self, project: Project) -> Result<(), Error> {
+        let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
+            return Err(Error::ProjectNotFound(project.id.to_string()));
+        };
+
+        // Activities, repos and their counts are removed by the database as
+        // part of the same statement.
         project.delete(&self.conn)?;
 
         Ok(())
     }
 
     pub fn update_project(&self, project: &Project) -> Result<(), Error> {
-        let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
+        if Project::get_with_id(project.id, &self.conn)?.is_none() {
             return Err(Error::ProjectNotFound(project.id.to_string()));
-        };
+        }
+
+        if Project::get_with_name(&project.name, &self.conn)?
+            .iter()
+            .any(|p| p.id != project.id)
+        {
+            return Err(Error::ProjectAlreadyExists(project.name.clone()));
+        }
+
+        if let Some(parent) = project.parent {
+            if Project::get_with_id(parent, &self.conn)?.is_none() {
+                return Err(Error::ProjectNotFound(parent.to_string()));
+            }
+
+            if parent == project.id
+                || Project::get_descendants(project.id, &self.conn)?
+                    .into_iter()
+                    .collect::<Result<Vec<_>, _>>()?
+                    .iter()
+                    .any(|p| p.id == parent)
+            {
+                return Err(Error::ProjectCycle(project.name.clone()));
+            }
+        }
 
         project.update(&self.conn)?;
 
@@ -115,11 +346,32 @@ impl DataStore {
         Ok(project)
     }
 
-    pub fn get_projects(&self) -> Result<Vec<Project>, Error> {
+    /// Every project, with an error in place of any that cannot be read.
+    pub fn get_projects(&self) -> Result<Vec<Result<Project, Error>>, Error> {
         let projects = Project::get_all(&self.conn)?;
 
         Ok(projects)
     }
+
+    /// The direct sub-projects of `project`.
+    pub fn get_child_projects(
+        &self,
+        project: &Project,
+    ) -> Result<Vec<Result<Project, Error>>, Error> {
+        let projects = Project::get_with_parent(project.id, &self.conn)?;
+
+        Ok(projects)
+    }
+
+    /// All of the sub-projects below `project`, however deeply nested.
+    pub fn get_descendant_projects(
+        &self,
+        project: &Project,
+    ) -> Result<Vec<Result<Project, Error>>, Error> {
+        let projects = Project::get_descendants(project.id, &self.conn)?;
+
+        Ok(projects)
+    }
 }
 
 // ActivityType
@@ -144,26 +396,54 @@ impl DataStore {
             return Err(Error::ActivityTypeNotFound(at.id.to_string()));
         };
 
-        let activities: Vec<Activity> = Activity::get_all(&self.conn)?
-            .into_iter()
-            .filter(|a| a.atype == at.id)
-            .collect();
-
-        for mut activity in activities {
-            activity.set_atype(0);
-            activity.update(&self.conn)?;
+        if at.id == 0 {
+            return Err(Error::ActivityTypeInUse(at.name));
         }
 
+        // Activities of this type are reassigned to the 'Unknown' activity
+        // type by the database as part of the same statement.
         at.delete(&self.conn)?;
 
         Ok(())
     }
 
-    pub fn update_activitytype(&self, at: &ActivityType) -> Result<(), Error> {
-        let Some(at) = ActivityType::get_with_id(at.id, &self.conn)? else {
+    /// Archives `at`, which hides it from listings and stops it being used
+    /// for new activities but keeps it for existing ones.
+    pub fn archive_activitytype(&self, at: &ActivityType) -> Result<(), Error> {
+        self.set_activitytype_archived(at, true)
+    }
+
+    pub fn unarchive_activitytype(&self, at: &ActivityType) -> Result<(), Error> {
+        self.set_activitytype_archived(at, false)
+    }
+
+    fn set_activitytype_archived(&self, at: &ActivityType, archived: bool) -> Result<(), Error> {
+        let Some(mut at) = ActivityType::get_with_id(at.id, &self.conn)? else {
             return Err(Error::ActivityTypeNotFound(at.id.to_string()));
         };
 
+        if at.id == 0 {
+            return Err(Error::ActivityTypeInUse(at.name));
+        }
+
+        at.archived = archived;
+        at.update(&self.conn)?;
+
+        Ok(())
+    }
+
+    pub fn update_activitytype(&self, at: &ActivityType) -> Result<(), Error> {
+        if ActivityType::get_with_id(at.id, &self.conn)?.is_none() {
+            return Err(Error::ActivityTypeNotFound(at.id.to_string()));
+        }
+
+        if ActivityType::get_with_name(&at.name, &self.conn)?
+            .iter()
+            .any(|a| a.id != at.id)
+        {
+            return Err(Error::ActivityTypeAlreadyExists(at.name.clone()));
+        }
+
         at.update(&self.conn)?;
 
         Ok(())
@@ -184,7 +464,9 @@ impl DataStore {
         Ok(at)
     }
 
-    pub fn get_activitytypes(&self) -> Result<Vec<ActivityType>, Error> {
+    /// Every activity type, with an error in place of any that cannot be
+    /// read.
+    pub fn get_activitytypes(&self) -> Result<Vec<Result<ActivityType, Error>>, Error> {
         let ats = ActivityType::get_all(&self.conn)?;
 
         Ok(ats)
@@ -198,6 +480,40 @@ impl DataStore {
         project: &Project,
         at: &ActivityType,
         description: Option<String>,
+        tags: &[String],
+    ) -> Result<Activity, Error> {
+        self.start_activity_at(project, at, description, tags, Utc::now())
+    }
+
+    /// Starts an activity for `project` at `start`, tagged with `tags`.  If
+    /// the running policy is `Refuse` this fails while another project has a
+    /// running activity, if it is `AutoStop` those activities are stopped at
+    /// `start`.  Nothing is changed if any of the tags is invalid.
+    pub fn start_activity_at(
+        &self,
+        project: &Project,
+        at: &ActivityType,
+        description: Option<String>,
+        tags: &[String],
+        start: DateTime<Utc>,
+    ) -> Result<Activity, Error> {
+        let tags = tag_names(tags)?;
+
+        let tx = self.conn.unchecked_transaction()?;
+        let activity = self.begin_activity(project, at, description, start)?;
+        self.add_activity_tags(&activity, &tags)?;
+        tx.commit()?;
+
+        Ok(activity)
+    }
+
+    // Callers are responsible for wrapping this in a transaction.
+    fn begin_activity(
+        &self,
+        project: &Project,
+        at: &ActivityType,
+        description: Option<String>,
+        start: DateTime<Utc>,
     ) -> Result<Activity, Error> {
         let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
             return Err(Error::ProjectNotFound(project.id.to_string()));
@@ -210,9 +526,60 @@ impl DataStore {
         let Some(at) = ActivityType::get_with_id(at.id, &self.conn)? else {
             return Err(Error::ActivityTypeNotFound(at.id.to_string()));
         };
+        check_not_archived(&project, &at)?;
+
+        let others = self.get_running_activities()?;
+        match self.running_policy()? {
+            RunningPolicy::PerProject => {}
+            RunningPolicy::Refuse => {
+                if let Some(other) = others.first() {
+                    return Err(Error::RunningActivityInOtherProject(
+                        other.project.to_string(),
+                    ));
+                }
+            }
+            RunningPolicy::AutoStop => {
+                for other in others {
+                    self.finish_activity(other, start)?;
+                }
+            }
+        }
+
+        let mut activity = Activity::with_times(project.id, at.id, description, start, None);
+        self.check_activity_times(&activity)?;
+        activity.create(&self.conn)?;
+
+        Ok(activity)
+    }
+
+    /// Records an activity that ran from `start` until `end`, tagged with
+    /// `tags`.  Nothing is changed if any of the tags is invalid.
+    pub fn create_activity(
+        &self,
+        project: &Project,
+        at: &ActivityType,
+        description: Option<String>,
+        tags: &[String],
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Activity, Error> {
+        let tags = tag_names(tags)?;
+
+        let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
+            return Err(Error::ProjectNotFound(project.id.to_string()));
+        };
+
+        let Some(at) = ActivityType::get_with_id(at.id, &self.conn)? else {
+            return Err(Error::ActivityTypeNotFound(at.id.to_string()));
+        };
+        check_not_archived(&project, &at)?;
 
-        let activity = Activity::new(project.id, at.id, description);
+        let mut activity = Activity::with_times(project.id, at.id, description, start, Some(end));
+        self.check_activity_times(&activity)?;
+        let tx = self.conn.unchecked_transaction()?;
         activity.create(&self.conn)?;
+        self.add_activity_tags(&activity, &tags)?;
+        tx.commit()?;
 
         Ok(activity)
     }
@@ -222,7 +589,7 @@ impl DataStore {
             return Err(Error::ProjectNotFound(project.id.to_string()));
         };
 
-        let Some(activity) = self.stop_running_activity(&project, None)? else {
+        let Some(activity) = self.get_running_activity(&project)? else {
             return Ok(());
         };
 
@@ -235,36 +602,302 @@ impl DataStore {
         &self,
         project: &Project,
         description: Option<String>,
+    ) -> Result<Option<Activity>, Error> {
+        self.stop_running_activity_at(project, description, Utc::now())
+    }
+
+    pub fn stop_running_activity_at(
+        &self,
+        project: &Project,
+        description: Option<String>,
+        end: DateTime<Utc>,
     ) -> Result<Option<Activity>, Error> {
         let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
             return Err(Error::ProjectNotFound(project.id.to_string()));
         };
 
-        let mut activities: Vec<_> = Activity::get_if_running(&self.conn)?
-            .into_iter()
-            .filter(|a| a.project == project.id)
-            .collect();
+        let mut activities = Activity::get_running_with_project(project.id, &self.conn)?;
 
         if activities.len() == 1 {
             let mut activity = activities.remove(0);
-            activity.end = Some(Utc::now());
             activity.description = description;
-            activity.update(&self.conn)?;
+
+            let tx = self.conn.unchecked_transaction()?;
+            let activity = self.finish_activity(activity, end)?;
+            tx.commit()?;
+
             return Ok(Some(activity));
         }
 
         Ok(None)
     }
 
+    /// Stops whatever activities are running, in any project, and starts a new
+    /// activity for `project` at the same instant.  Either everything succeeds
+    /// or nothing is changed.
+    pub fn switch_activity(
+        &self,
+        project: &Project,
+        at: &ActivityType,
+        description: Option<String>,
+    ) -> Result<Activity, Error> {
+        let now = Utc::now();
+
+        let tx = self.conn.unchecked_transaction()?;
+        for activity in self.get_running_activities()? {
+            self.finish_activity(activity, now)?;
+        }
+        let activity = self.begin_activity(project, at, description, now)?;
+        tx.commit()?;
+
+        Ok(activity)
+    }
+
+    /// Starts a new activity with the same project, activity type, and
+    /// description as the most recently started finished activity, either in
+    /// `project` or in any project.  Returns `None` if there is no finished
+    /// activity to copy.
+    pub fn resume_last_activity(
+        &self,
+        project: Option<&Project>,
+    ) -> Result<Option<Activity>, Error> {
+        let mut query = ActivityQuery::new()
+            .running(false)
+            .order_by(ActivityOrder::Start)
+            .reverse(true)
+            .limit(1);
+        if let Some(project) = project {
+            let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
+                return Err(Error::ProjectNotFound(project.id.to_string()));
+            };
+            query = query.project(&project);
+        }
+
+        let tx = self.conn.unchecked_transaction()?;
+        let Some(last) = Activity::query(&query, &tx)?.into_iter().next() else {
+            return Ok(None);
+        };
+        let last = last?;
+        let Some(project) = Project::get_with_id(last.project, &tx)? else {
+            return Err(Error::ProjectNotFound(last.project.to_string()));
+        };
+        let Some(at) = ActivityType::get_with_id(last.atype, &tx)? else {
+            return Err(Error::ActivityTypeNotFound(last.atype.to_string()));
+        };
+        let activity = self.begin_activity(&project, &at, last.description, Utc::now())?;
+        tx.commit()?;
+
+        Ok(Some(activity))
+    }
+
+    /// Splits `activity` in two at `at`.  The activity keeps its id and the
+    /// part before `at`, the part from `at` onwards becomes a new activity
+    /// with an activity type of `at_type`, `description`, and the same tags,
+    /// which is returned.  If the activity is running the new activity is
+    /// running.
+    pub fn split_activity(
+        &self,
+        activity: &Activity,
+        at: DateTime<Utc>,
+        atype: &ActivityType,
+        description: Option<String>,
+    ) -> Result<Activity, Error> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let Some(mut first) = Activity::get_with_id(activity.id, &tx)? else {
+            return Err(Error::ActivityNotFound(activity.id.to_string()));
+        };
+        let Some(atype) = ActivityType::get_with_id(atype.id, &tx)? else {
+            return Err(Error::ActivityTypeNotFound(atype.id.to_string()));
+        };
+        if atype.archived && atype.id != first.atype {
+            return Err(Error::ActivityTypeArchived(atype.name));
+        }
+        if at <= first.start || at >= first.end.unwrap_or_else(Utc::now) {
+            return Err(Error::SplitOutsideActivity(at.to_rfc3339()));
+        }
+
+        let mut second = Activity::with_times(first.project, atype.id, description, at, first.end);
+        second.create(&tx)?;
+        for tag in Tag::get_with_activity(first.id, &tx)? {
+            tag.add_to_activity(second.id, &tx)?;
+        }
+
+        // A pause that spans the split carries on into the new activity.
+        for pause in Pause::get_with_activity(first.id, &tx)? {
+            if pause.start < at && pause.end.is_none_or(|end| end > at) {
+                let mut rest = Pause::new(second.id, at);
+                rest.end = pause.end;
+                rest.create(&tx)?;
+            }
+        }
+        Pause::move_to_activity(first.id, second.id, at, &tx)?;
+        Pause::trim_with_activity(first.id, at, &tx)?;
+
+        first.end = Some(at);
+        first.update(&tx)?;
+        tx.commit()?;
+
+        Activity::get_with_id(second.id, &self.conn)?
+            .ok_or_else(|| Error::ActivityNotFound(second.id.to_string()))
+    }
+
+    /// Merges `activities` from the same project into the earliest of them,
+    /// which keeps its id, activity type, and the descriptions and tags of all
+    /// of them.
+    /// Gaps between the activities are recorded as pauses so the total time is
+    /// unchanged.  Fails if another activity in the project falls between
+    /// them, or if any but the last activity is still running.
+    pub fn merge_activities(&self, activities: &[Activity]) -> Result<Activity, Error> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let mut merging = Vec::new();
+        for activity in activities {
+            let Some(activity) = Activity::get_with_id(activity.id, &tx)? else {
+                return Err(Error::ActivityNotFound(activity.id.to_string()));
+            };
+            if !merging.iter().any(|m: &Activity| m.id == activity.id) {
+                merging.push(activity);
+            }
+        }
+        merging.sort_by_key(|a| (a.start, a.id));
+
+        let mut rest = merging.into_iter();
+        let Some(mut first) = rest.next() else {
+            return Err(Error::NothingToMerge);
+        };
+        let rest: Vec<_> = rest.collect();
+
+        for activity in &rest {
+            if activity.project != first.project {
+                return Err(Error::ActivitiesInDifferentProjects(format!(
+                    "{}, {}",
+                    first.id, activity.id
+                )));
+            }
+        }
+
+        let mut descriptions: Vec<String> = first.description.iter().cloned().collect();
+        // The activity whose end the merged activity has so far.
+        let mut last = first.id;
+        for activity in rest {
+            let Some(end) = first.end else {
+                return Err(Error::ActivityStillRunning(last.to_string()));
+            };
+            if activity.start < end {
+                return Err(Error::ActivityOverlaps(activity.id.to_string()));
+            }
+            if activity.start > end {
+                let mut gap = Pause::new(first.id, end);
+                gap.end = Some(activity.start);
+                gap.create(&tx)?;
+            }
+            Pause::move_to_activity(activity.id, first.id, activity.start, &tx)?;
+            for tag in Tag::get_with_activity(activity.id, &tx)? {
+                tag.add_to_activity(first.id, &tx)?;
+            }
+
+            first.end = activity.end;
+            last = activity.id;
+            if let Some(description) = &activity.description {
+                if !descriptions.contains(description) {
+                    descriptions.push(description.clone());
+                }
+            }
+            activity.delete(&tx)?;
+        }
+
+        first.description = if descriptions.is_empty() {
+            None
+        } else {
+            Some(descriptions.join("; "))
+        };
+
+        self.check_activity_times(&first)?;
+        first.update(&tx)?;
+        tx.commit()?;
+
+        Activity::get_with_id(first.id, &self.conn)?
+            .ok_or_else(|| Error::ActivityNotFound(first.id.to_string()))
+    }
+
+    // Sets the end of a running activity, ending the pause if it is paused.
+    // Callers are responsible for wrapping this in a transaction.
+    fn finish_activity(
+        &self,
+        mut activity: Activity,
+        end: DateTime<Utc>,
+    ) -> Result<Activity, Error> {
+        activity.end = Some(end);
+        self.check_activity_times(&activity)?;
+
+        if let Some(mut pause) = Pause::get_running_with_activity(activity.id, &self.conn)? {
+            if end < pause.start {
+                return Err(Error::ActivityEndBeforeStart(end.to_rfc3339()));
+            }
+            pause.end = Some(end);
+            pause.update(&self.conn)?;
+        }
+        activity.update(&self.conn)?;
+
+        Activity::get_with_id(activity.id, &self.conn)?
+            .ok_or_else(|| Error::ActivityNotFound(activity.id.to_string()))
+    }
+
+    /// Pauses the running activity for `project`, time spent paused is not
+    /// counted in the duration of the activity.  Returns `None` if there is no
+    /// running activity.
+    pub fn pause_running_activity(&self, project: &Project) -> Result<Option<Activity>, Error> {
+        let Some(activity) = self.get_running_activity(project)? else {
+            return Ok(None);
+        };
+
+        if activity.is_paused() {
+            return Err(Error::ActivityAlreadyPaused(activity.id.to_string()));
+        }
+
+        let now = Utc::now();
+        if now < activity.start {
+            return Err(Error::ActivityEndBeforeStart(now.to_rfc3339()));
+        }
+
+        let mut pause = Pause::new(activity.id, now);
+        pause.create(&self.conn)?;
+
+        Activity::get_with_id(activity.id, &self.conn)
+    }
+
+    /// Resumes the paused running activity for `project`.  Returns `None` if
+    /// there is no running activity.
+    pub fn resume_running_activity(&self, project: &Project) -> Result<Option<Activity>, Error> {
+        let Some(activity) = self.get_running_activity(project)? else {
+            return Ok(None);
+        };
+
+        let Some(mut pause) = Pause::get_running_with_activity(activity.id, &self.conn)? else {
+            return Err(Error::ActivityNotPaused(activity.id.to_string()));
+        };
+
+        pause.end = Some(Utc::now());
+        pause.update(&self.conn)?;
+
+        Activity::get_with_id(activity.id, &self.conn)
+    }
+
+    pub fn get_pauses(&self, activity: &Activity) -> Result<Vec<Pause>, Error> {
+        let Some(activity) = Activity::get_with_id(activity.id, &self.conn)? else {
+            return Err(Error::ActivityNotFound(activity.id.to_string()));
+        };
+
+        Pause::get_with_activity(activity.id, &self.conn)
+    }
+
     pub fn get_running_activity(&self, project: &Project) -> Result<Option<Activity>, Error> {
         let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
             return Err(Error::ProjectNotFound(project.id.to_string()));
         };
 
-        let mut activities: Vec<_> = Activity::get_if_running(&self.conn)?
-            .into_iter()
-            .filter(|a| a.project == project.id)
-            .collect();
+        let mut activities = Activity::get_running_with_project(project.id, &self.conn)?;
 
         if activities.len() == 1 {
             Ok(Some(activities.remove(0)))
@@ -273,6 +906,13 @@ impl DataStore {
         }
     }
 
+    /// The running activities in every project, oldest first.
+    pub fn get_running_activities(&self) -> Result<Vec<Activity>, Error> {
+        Activity::query(&ActivityQuery::new().running(true), &self.conn)?
+            .into_iter()
+            .collect()
+    }
+
     pub fn get_activity_with_id(&self, id: u64) -> Result<Option<Activity>, Error> {
         let activity = Activity::get_with_id(id, &self.conn)?;
         Ok(activity)
@@ -289,27 +929,353 @@ impl DataStore {
     }
 
     pub fn update_activity(&self, activity: &Activity) -> Result<(), Error> {
-        let Some(activity) = Activity::get_with_id(activity.id, &self.conn)? else {
+        let Some(existing) = Activity::get_with_id(activity.id, &self.conn)? else {
             return Err(Error::ActivityNotFound(activity.id.to_string()));
         };
 
+        if Project::get_with_id(activity.project, &self.conn)?.is_none() {
+            return Err(Error::ProjectNotFound(activity.project.to_string()));
+        }
+
+        if ActivityType::get_with_id(activity.atype, &self.conn)?.is_none() {
+            return Err(Error::ActivityTypeNotFound(activity.atype.to_string()));
+        }
+
+        if existing.project != activity.project
+            || existing.start != activity.start
+            || existing.end != activity.end
+        {
+            self.check_activity_times(activity)?;
+        }
+
         activity.update(&self.conn)?;
 
         Ok(())
     }
 
-    pub fn get_activities(&self, project: &Project) -> Result<Vec<Activity>, Error> {
+    fn check_activity_times(&self, activity: &Activity) -> Result<(), Error> {
+        if let Some(end) = activity.end {
+            if end < activity.start {
+                return Err(Error::ActivityEndBeforeStart(end.to_rfc3339()));
+            }
+        }
+
+        let overlapping = Activity::get_overlapping(
+            activity.project,
+            activity.start,
+            activity.end,
+            activity.id,
+            &self.conn,
+        )?;
+        if let Some(other) = overlapping.first() {
+            return Err(Error::ActivityOverlaps(other.id.to_string()));
+        }
+
+        Ok(())
+    }
+
+    /// The activities in `project`, with an error in place of any that
+    /// cannot be read.
+    pub fn get_activities(&self, project: &Project) -> Result<Vec<Result<Activity, Error>>, Error> {
         let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
             return Err(Error::ProjectNotFound(project.id.to_string()));
         };
 
-        let activities = Activity::get_all(&self.conn)?
-            .into_iter()
-            .filter(|a| a.project == project.id)
-            .collect();
+        let query = ActivityQuery::new().project(&project);
+        let activities = Activity::query(&query, &self.conn)?;
 
         Ok(activities)
     }
+
+    pub fn query_activities(
+        &self,
+        query: &ActivityQuery,
+    ) -> Result<impl Iterator<Item = Result<Activity, Error>>, Error> {
+        let activities = Activity::query(query, &self.conn)?;
+
+        Ok(activities.into_iter())
+    }
+}
+
+// Tags
+impl DataStore {
+    /// Adds the tags with `names` to `activity`.  Either every tag is added
+    /// or, if any name is invalid, none are.
+    pub fn tag_activity(&self, activity: &Activity, names: &[String]) -> Result<(), Error> {
+        let names = tag_names(names)?;
+
+        let Some(activity) = Activity::get_with_id(activity.id, &self.conn)? else {
+            return Err(Error::ActivityNotFound(activity.id.to_string()));
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        self.add_activity_tags(&activity, &names)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    // Callers are responsible for checking the tag names and wrapping this in
+    // a transaction.
+    fn add_activity_tags(&self, activity: &Activity, names: &[String]) -> Result<(), Error> {
+        for name in names {
+            Tag::get_or_create(name, &self.conn)?.add_to_activity(activity.id, &self.conn)?;
+        }
+
+        Ok(())
+    }
+
+    /// Removes the tags with `names` from `activity`.  Either every tag is
+    /// removed or, if any of them does not exist, none are.
+    pub fn untag_activity(&self, activity: &Activity, names: &[String]) -> Result<(), Error> {
+        let Some(activity) = Activity::get_with_id(activity.id, &self.conn)? else {
+            return Err(Error::ActivityNotFound(activity.id.to_string()));
+        };
+
+        let tags = self.existing_tags(names)?;
+        let tx = self.conn.unchecked_transaction()?;
+        for tag in tags {
+            tag.remove_from_activity(activity.id, &tx)?;
+        }
+        Tag::delete_unused(&tx)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Adds the tags with `names` to `project`.  Either every tag is added or,
+    /// if any name is invalid, none are.
+    pub fn tag_project(&self, project: &Project, names: &[String]) -> Result<(), Error> {
+        let names = tag_names(names)?;
+
+        let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
+            return Err(Error::ProjectNotFound(project.id.to_string()));
+        };
+
+        let tx = self.conn.unchecked_transaction()?;
+        for name in names {
+            Tag::get_or_create(&name, &tx)?.add_to_project(project.id, &tx)?;
+        }
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    /// Removes the tags with `names` from `project`.  Either every tag is
+    /// removed or, if any of them does not exist, none are.
+    pub fn untag_project(&self, project: &Project, names: &[String]) -> Result<(), Error> {
+        let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
+            return Err(Error::ProjectNotFound(project.id.to_string()));
+        };
+
+        let tags = self.existing_tags(names)?;
+        let tx = self.conn.unchecked_transaction()?;
+        for tag in tags {
+            tag.remove_from_project(project.id, &tx)?;
+        }
+        Tag::delete_unused(&tx)?;
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn existing_tags(&self, names: &[String]) -> Result<Vec<Tag>, Error> {
+        names
+            .iter()
+            .map(|name| {
+                Tag::get_with_name(name.trim(), &self.conn)?
+                    .ok_or_else(|| Error::TagNotFound(name.to_string()))
+            })
+            .collect()
+    }
+
+    pub fn get_activity_tags(&self, activity: &Activity) -> Result<Vec<Tag>, Error> {
+        Tag::get_with_activity(activity.id, &self.conn)
+    }
+
+    /// The tags of each of `activities`, keyed by activity ID, found with one
+    /// query.  Activities without tags are left out.
+    pub fn get_tags_for_activities(
+        &self,
+        activities: &[Activity],
+    ) -> Result<HashMap<u64, Vec<Tag>>, Error> {
+        let ids: Vec<_> = activities.iter().map(|a| a.id).collect();
+        Tag::get_with_activities(&ids, &self.conn)
+    }
+
+    pub fn get_project_tags(&self, project: &Project) -> Result<Vec<Tag>, Error> {
+        Tag::get_with_project(project.id, &self.conn)
+    }
+
+    pub fn get_tags(&self) -> Result<Vec<Result<Tag, Error>>, Error> {
+        Tag::get_all(&self.conn)
+    }
+}
+
+// New activities can only be recorded against projects and activity types
+// that have not been archived.
+fn check_not_archived(project: &Project, at: &ActivityType) -> Result<(), Error> {
+    if project.status == ProjectStatus::Archived {
+        return Err(Error::ProjectArchived(project.name.clone()));
+    }
+
+    if at.archived {
+        return Err(Error::ActivityTypeArchived(at.name.clone()));
+    }
+
+    Ok(())
+}
+
+// Tags are free-form but must not be empty or contain whitespace or commas,
+// so they can be written in lists on the command line.
+fn tag_name(name: &str) -> Result<String, Error> {
+    let name = name.trim();
+    if name.is_empty() || name.contains(|c: char| c.is_whitespace() || c == ',') {
+        return Err(Error::InvalidTag(name.to_string()));
+    }
+    Ok(name.to_string())
+}
+
+fn tag_names(names: &[String]) -> Result<Vec<String>, Error> {
+    names.iter().map(|name| tag_name(name)).collect()
+}
+
+// Overlaps
+impl DataStore {
+    /// Finds every pair of activities whose time spans overlap, in any
+    /// project or only those involving `project`, ordered by the start of the
+    /// first activity.
+    pub fn find_overlaps(&self, project: Option<&Project>) -> Result<Vec<Overlap>, Error> {
+        let now = Utc::now();
+        let pairs = Activity::get_overlapping_pairs(project.map(|p| p.id), now, &self.conn)?;
+
+        // An activity can overlap several others, so each is only read once.
+        let mut activities: HashMap<u64, Activity> = HashMap::new();
+        let mut overlaps = Vec::new();
+        for (first, second) in pairs {
+            for id in [first, second] {
+                if let Entry::Vacant(entry) = activities.entry(id) {
+                    let activity = Activity::get_with_id(id, &self.conn)?
+                        .ok_or_else(|| Error::ActivityNotFound(id.to_string()))?;
+                    entry.insert(activity);
+                }
+            }
+            overlaps.push(Overlap {
+                first: activities[&first].clone(),
+                second: activities[&second].clone(),
+                found: now,
+            });
+        }
+
+        Ok(overlaps)
+    }
+
+    /// Removes an overlap found by `find_overlaps`, returns the activity
+    /// created when a split leaves part of the first activity after the
+    /// second.
+    pub fn resolve_overlap(
+        &self,
+        overlap: &Overlap,
+        resolution: OverlapResolution,
+    ) -> Result<Option<Activity>, Error> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let Some(mut first) = Activity::get_with_id(overlap.first.id, &tx)? else {
+            return Err(Error::ActivityNotFound(overlap.first.id.to_string()));
+        };
+        let Some(second) = Activity::get_with_id(overlap.second.id, &tx)? else {
+            return Err(Error::ActivityNotFound(overlap.second.id.to_string()));
+        };
+
+        // The activities may have changed since the overlap was found, and
+        // resolving one that has gone could lengthen the first activity.
+        let overlaps =
+            second.start >= first.start && first.end.is_none_or(|end| end > second.start);
+        if !overlaps {
+            return Err(Error::NoLongerOverlaps(format!(
+                "{}, {}",
+                first.id, second.id
+            )));
+        }
+
+        let mut created = None;
+        match resolution {
+            OverlapResolution::Trim => {
+                first.end = Some(second.start);
+                Pause::trim_with_activity(first.id, second.start, &tx)?;
+            }
+            OverlapResolution::Merge => {
+                if first.project != second.project {
+                    return Err(Error::ActivitiesInDifferentProjects(format!(
+                        "{}, {}",
+                        first.id, second.id
+                    )));
+                }
+                if first.atype != second.atype {
+                    return Err(Error::ActivitiesOfDifferentTypes(format!(
+                        "{}, {}",
+                        first.id, second.id
+                    )));
+                }
+                let end = match (first.end, second.end) {
+                    (Some(first_end), Some(second_end)) => Some(first_end.max(second_end)),
+                    _ => None,
+                };
+                Pause::move_to_activity(second.id, first.id, second.start, &tx)?;
+                for tag in Tag::get_with_activity(second.id, &tx)? {
+                    tag.add_to_activity(first.id, &tx)?;
+                }
+                if let Some(description) = &second.description {
+                    first.description = match first.description.take() {
+                        Some(first_description) if first_description != *description => {
+                            Some(format!("{}; {}", first_description, description))
+                        }
+                        first_description => first_description.or(Some(description.clone())),
+                    };
+                }
+                second.delete(&tx)?;
+
+                // Only the time the first activity is extended by can
+                // overlap anything that it did not already overlap.
+                if let Some(first_end) = first.end {
+                    if end.is_none_or(|end| end > first_end) {
+                        let extension =
+                            Activity::with_times(first.project, first.atype, None, first_end, end);
+                        self.check_activity_times(&extension)?;
+                    }
+                }
+                first.end = end;
+            }
+            OverlapResolution::Split => {
+                let carries_on = match (first.end, second.end) {
+                    (None, _) => true,
+                    (Some(first_end), Some(second_end)) => first_end > second_end,
+                    (Some(_), None) => false,
+                };
+                if let (true, Some(second_end)) = (carries_on, second.end) {
+                    let mut rest = Activity::with_times(
+                        first.project,
+                        first.atype,
+                        first.description.clone(),
+                        second_end,
+                        first.end,
+                    );
+                    rest.create(&tx)?;
+                    Pause::move_to_activity(first.id, rest.id, second_end, &tx)?;
+                    for tag in Tag::get_with_activity(first.id, &tx)? {
+                        tag.add_to_activity(rest.id, &tx)?;
+                    }
+                    created = Activity::get_with_id(rest.id, &tx)?;
+                }
+                first.end = Some(second.start);
+                Pause::trim_with_activity(first.id, second.start, &tx)?;
+            }
+        }
+        first.update(&tx)?;
+        tx.commit()?;
+
+        Ok(created)
+    }
 }
 
 // Repos
@@ -319,6 +1285,10 @@ impl DataStore {
             return Err(Error::ProjectNotFound(project.id.to_string()));
         };
 
+        if !Repo::get_with_path(path, &self.conn)?.is_empty() {
+            return Err(Error::RepoAlreadyExists(path.display().to_string()));
+        }
+
         let repo = Repo::new(PathBuf::from(path), project.id);
         repo.create(&self.conn)?;
 
@@ -345,39 +1315,58 @@ impl DataStore {
             return Err(Error::RepoNotFound(repo.id.to_string()));
         };
 
-        let counts: Vec<_> = Count::get_all(&self.conn)?
-            .into_iter()
-            .filter(|c| c.repo == repo.id)
-            .collect();
+        // Counts are removed by the database as part of the same statement.
+        repo.delete(&self.conn)?;
 
-        for count in counts {
-            self.delete_count(count)?;
-        }
+        Ok(())
+    }
 
-        repo.delete(&self.conn)?;
+    /// Archives `repo`, which hides it from listings and stops it being
+    /// counted but keeps its existing counts.
+    pub fn archive_repo(&self, repo: &Repo) -> Result<(), Error> {
+        self.set_repo_archived(repo, true)
+    }
+
+    pub fn unarchive_repo(&self, repo: &Repo) -> Result<(), Error> {
+        self.set_repo_archived(repo, false)
+    }
+
+    fn set_repo_archived(&self, repo: &Repo, archived: bool) -> Result<(), Error> {
+        let Some(mut repo) = Repo::get_with_id(repo.id, &self.conn)? else {
+            return Err(Error::RepoNotFound(repo.id.to_string()));
+        };
+
+        repo.archived = archived;
+        repo.update(&self.conn)?;
 
         Ok(())
     }
 
     pub fn update_repo(&self, repo: &Repo) -> Result<(), Error> {
-        let Some(repo) = Repo::get_with_id(repo.id, &self.conn)? else {
+        if Repo::get_with_id(repo.id, &self.conn)?.is_none() {
             return Err(Error::RepoNotFound(repo.id.to_string()));
-        };
+        }
+
+        if Repo::get_with_path(&repo.path, &self.conn)?
+            .iter()
+            .any(|r| r.id != repo.id)
+        {
+            return Err(Error::RepoAlreadyExists(repo.path.display().to_string()));
+        }
 
         repo.update(&self.conn)?;
 
         Ok(())
     }
 
-    pub fn get_repos(&self, project: &Project) -> Result<Vec<Repo>, Error> {
+    /// The repositories in `project`, with an error in place of any that
+    /// cannot be read.
+    pub fn get_repos(&self, project: &Project) -> Result<Vec<Result<Repo, Error>>, Error> {
         let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
             return Err(Error::ProjectNotFound(project.id.to_string()));
         };
 
-        let repos = Repo::get_all(&self.conn)?
-            .into_iter()
-            .filter(|r| r.project == project.id)
-            .collect();
+        let repos = Repo::get_with_project(project.id, &self.conn)?;
 
         Ok(repos)
     }
@@ -389,7 +1378,10 @@ impl DataStore {
         let Some(repo) = Repo::get_with_id(repo.id, &self.conn)? else {
             return Err(Error::RepoNotFound(repo.id.to_string()));
         };
-        let paths = vec![repo.path.display().to_string()];
+        if repo.archived {
+            return Err(Error::RepoArchived(repo.path.display().to_string()));
+        }
+        let paths = [repo.path.display().to_string()];
 
         let date = Utc::now();
 
@@ -410,23 +1402,12 @@ impl DataStore {
     }
 
     pub fn get_latest_count(&self, repo: &Repo) -> Result<Option<Count>, Error> {
-        let mut counts = self.get_counts(repo)?;
-        #[allow(clippy::comparison_chain)]
-        counts.sort_by(|a, b| {
-            if a.date < b.date {
-                Ordering::Less
-            } else if a.date == b.date {
-                Ordering::Equal
-            } else {
-                Ordering::Greater
-            }
-        });
+        let Some(repo) = Repo::get_with_id(repo.id, &self.conn)? else {
+            return Err(Error::RepoNotFound(repo.id.to_string()));
+        };
 
-        if !counts.is_empty() {
-            Ok(Some(counts.remove(counts.len() - 1)))
-        } else {
-            Ok(None)
-        }
+        let count = Count::get_latest_with_repo(repo.id, &self.conn)?;
+        Ok(count)
     }
 
     pub fn delete_count(&self, count: Count) -> Result<(), Error> {
@@ -439,15 +1420,14 @@ impl DataStore {
         Ok(())
     }
 
-    pub fn get_counts(&self, repo: &Repo) -> Result<Vec<Count>, Error> {
+    /// The counts for `repo`, oldest first, with an error in place of any
+    /// that cannot be read.
+    pub fn get_counts(&self, repo: &Repo) -> Result<Vec<Result<Count, Error>>, Error> {
         let Some(repo) = Repo::get_with_id(repo.id, &self.conn)? else {
             return Err(Error::RepoNotFound(repo.id.to_string()));
         };
 
-        let counts = Count::get_all(&self.conn)?
-            .into_iter()
-            .filter(|c| c.repo == repo.id)
-            .collect();
+        let counts = Count::get_with_repo(repo.id, None, None, &self.conn)?;
 
         Ok(counts)
     }
@@ -461,13 +1441,31 @@ impl DataStore {
     }
 }
 
+// Settings
+impl DataStore {
+    pub fn running_policy(&self) -> Result<RunningPolicy, Error> {
+        match settings::get(settings::RUNNING_POLICY, &self.conn)? {
+            Some(value) => value.parse(),
+            None => Ok(RunningPolicy::default()),
+        }
+    }
+
+    pub fn set_running_policy(&self, policy: RunningPolicy) -> Result<(), Error> {
+        settings::set(settings::RUNNING_POLICY, &policy.to_string(), &self.conn)
+    }
+}
+
 // Report
 impl DataStore {
+    /// Creates a report on `project` between `start` and `end`, if `tag` is
+    /// given only activities with that tag, or in a project with that tag,
+    /// are included.
     pub fn create_report(
         &self,
         project: &Project,
         start: Option<DateTime<Utc>>,
         end: Option<DateTime<Utc>>,
+        tag: Option<&str>,
     ) -> Result<Report, Error> {
         let Some(project) = Project::get_with_id(project.id, &self.conn)? else {
             return Err(Error::ProjectNotFound(project.id.to_string()));
@@ -475,7 +1473,12 @@ impl DataStore {
 
         let mut report = Report::new(&project, start, end);
 
-        let activities = self.get_report_activities(&project, start, end)?;
+        let activities = self.get_report_activities(&project, start, end, tag)?;
+        for activity in &activities {
+            for tag in &activity.tags {
+                *report.tags.entry(tag.clone()).or_insert(0) += activity.minutes;
+            }
+        }
         report.activities = activities;
 
         let counts = self.get_report_counts(&project, start, end)?;
@@ -489,40 +1492,94 @@ impl DataStore {
         project: &Project,
         start: Option<DateTime<Utc>>,
         end: Option<DateTime<Utc>>,
+        tag: Option<&str>,
     ) -> Result<Vec<report::Activity>, Error> {
-        let activities = self.get_activities(&project)?;
+        // Time spent on sub-projects is rolled up into their parent.
+        let mut projects = vec![Project::get_with_id(project.id, &self.conn)?
+            .ok_or_else(|| Error::ProjectNotFound(project.id.to_string()))?];
+        for p in Project::get_descendants(project.id, &self.conn)? {
+            projects.push(p?);
+        }
 
-        let activities = match (start, end) {
-            (None, None) => activities,
-            (None, Some(end)) => activities.into_iter().filter(|a| a.start <= end).collect(),
-            (Some(start), None) => activities
-                .into_iter()
-                .filter(|a| a.start >= start)
-                .collect(),
-            (Some(start), Some(end)) => activities
+        let mut query = ActivityQuery::new();
+        for p in &projects {
+            query = query.project(p);
+        }
+        if let Some(tag) = tag {
+            query = query.tag(tag);
+        }
+        if let Some(start) = start {
+            query = query.since(start);
+        }
+        if let Some(end) = end {
+            query = query.until(end);
+        }
+        let activities = Activity::query(&query, &self.conn)?
+            .into_iter()
+            .collect::<Result<Vec<_>, _>>()?;
+        let ids: Vec<_> = activities.iter().map(|a| a.id).collect();
+        let mut activity_tags = Tag::get_with_activities(&ids, &self.conn)?;
+
+        let mut names = HashMap::new();
+        for at in ActivityType::get_all(&self.conn)? {
+            let at = at?;
+            names.insert(at.id, at.name);
+        }
+
+        let mut project_tags = HashMap::new();
+        for p in &projects {
+            let tags: Vec<_> = Tag::get_with_project(p.id, &self.conn)?
                 .into_iter()
-                .filter(|a| a.start >= start && a.start <= end)
-                .collect(),
-        };
+                .map(|t| t.name)
+                .collect();
+            project_tags.insert(p.id, tags);
+        }
 
-        let activities: Vec<_> = activities
-            .into_iter()
-            .map(|a| {
-                let at = ActivityType::get_with_id(a.atype, &self.conn)
-                    .expect("should always be able to access the datastore");
-                let name = at.map_or_else(|| "Unknown".to_string(), |at| at.name);
-                let start = a.start;
-                let end = a.end.unwrap_or(Utc::now());
-                let duration = end - start;
-                report::Activity {
-                    name,
-                    start,
-                    minutes: duration.num_minutes(),
+        // Activities are in order of their start time, time already covered
+        // by an earlier overlapping activity is not counted again.
+        let now = Utc::now();
+        let mut covered_until: Option<DateTime<Utc>> = None;
+        let mut report_activities = Vec::new();
+        for a in activities {
+            let mut tags: Vec<_> = activity_tags
+                .remove(&a.id)
+                .unwrap_or_default()
+                .into_iter()
+                .map(|t| t.name)
+                .collect();
+            for tag in &project_tags[&a.project] {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
                 }
-            })
-            .collect();
+            }
+            tags.sort();
+
+            let mut name = names
+                .get(&a.atype)
+                .cloned()
+                .unwrap_or_else(|| "Unknown".to_string());
+            if a.project != project.id {
+                if let Some(p) = projects.iter().find(|p| p.id == a.project) {
+                    name = format!("{} ({})", name, p.name);
+                }
+            }
+            let end = a.end.unwrap_or(now);
+            let overlap = match covered_until {
+                Some(covered) if covered > a.start => covered.min(end) - a.start,
+                _ => Duration::zero(),
+            };
+            covered_until = Some(covered_until.map_or(end, |covered| covered.max(end)));
+            report_activities.push(report::Activity {
+                name,
+                start: a.start,
+                minutes: (a.worked_until(end) - overlap)
+                    .max(Duration::zero())
+                    .num_minutes(),
+                tags,
+            });
+        }
 
-        Ok(activities)
+        Ok(report_activities)
     }
 
     fn get_report_counts(
@@ -533,19 +1590,15 @@ impl DataStore {
     ) -> Result<HashMap<String, Vec<report::Count>>, Error> {
         let mut all_counts = HashMap::new();
 
-        let repos = self.get_repos(&project)?;
+        let mut repos = Repo::get_with_project(project.id, &self.conn)?;
+        for p in Project::get_descendants(project.id, &self.conn)? {
+            repos.extend(Repo::get_with_project(p?.id, &self.conn)?);
+        }
         for repo in repos {
-            let counts = self.get_counts(&repo)?;
-
-            let counts = match (start, end) {
-                (None, None) => counts,
-                (None, Some(end)) => counts.into_iter().filter(|c| c.date <= end).collect(),
-                (Some(start), None) => counts.into_iter().filter(|c| c.date >= start).collect(),
-                (Some(start), Some(end)) => counts
-                    .into_iter()
-                    .filter(|c| c.date >= start && c.date <= end)
-                    .collect(),
-            };
+            let repo = repo?;
+            let counts: Vec<_> = Count::get_with_repo(repo.id, start, end, &self.conn)?
+                .into_iter()
+                .collect::<Result<_, _>>()?;
 
             let path = repo.path.display().to_string();
 
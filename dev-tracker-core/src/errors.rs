@@ -6,16 +6,46 @@ pub enum Error {
     SerdeJson(serde_json::Error),
     ProjectNotFound(String),
     ProjectAlreadyExists(String),
+    ProjectCycle(String),
+    ProjectArchived(String),
     ActivityTypeNotFound(String),
     ActivityTypeAlreadyExists(String),
     ActivityTypeInUse(String),
+    ActivityTypeArchived(String),
     RepoNotFound(String),
     RepoAlreadyExists(String),
+    RepoArchived(String),
     ActivityNotFound(String),
     ActivityAlreadyExists(String),
     RunningActivityAlreadyExists(String),
+    RunningActivityInOtherProject(String),
+    ActivityEndBeforeStart(String),
+    ActivityOverlaps(String),
+    NoLongerOverlaps(String),
+    ActivityAlreadyPaused(String),
+    ActivityNotPaused(String),
+    ActivityStillRunning(String),
+    ActivitiesInDifferentProjects(String),
+    ActivitiesOfDifferentTypes(String),
+    SplitOutsideActivity(String),
+    NothingToMerge,
     CountNotFound(String),
+    TagNotFound(String),
+    InvalidTag(String),
+    InvalidProjectStatus(String),
     ReportError(String),
+    SchemaTooNew(u32),
+    MigrationError(String),
+    HistoryError(String),
+    InvalidBackup(String),
+    BackupFailed(String),
+    DatabaseCorrupt(String),
+    CorruptRow {
+        table: String,
+        id: u64,
+        source: rusqlite::Error,
+    },
+    InvalidSetting(String),
 }
 
 impl Display for Error {
@@ -28,6 +58,11 @@ impl Display for Error {
                 "alreadyexists",
                 format!("project '{}' already exists", item),
             ),
+            Error::ProjectCycle(item) => (
+                "invalid",
+                format!("project '{}' cannot be a sub-project of itself", item),
+            ),
+            Error::ProjectArchived(item) => ("archived", format!("project '{}' is archived", item)),
             Error::ActivityTypeNotFound(item) => {
                 ("notfound", format!("activity type '{}' not found", item))
             }
@@ -38,10 +73,14 @@ impl Display for Error {
             Error::ActivityTypeInUse(item) => {
                 ("inuse", format!("activity type '{}' is in use", item))
             }
+            Error::ActivityTypeArchived(item) => {
+                ("archived", format!("activity type '{}' is archived", item))
+            }
             Error::RepoNotFound(item) => ("notfound", format!("repo '{}' not found", item)),
             Error::RepoAlreadyExists(item) => {
                 ("alreadyexists", format!("repo '{}' already exists", item))
             }
+            Error::RepoArchived(item) => ("archived", format!("repo '{}' is archived", item)),
             Error::ActivityNotFound(item) => ("notfound", format!("activity '{}' not found", item)),
             Error::ActivityAlreadyExists(item) => (
                 "alreadyexists",
@@ -51,8 +90,81 @@ impl Display for Error {
                 "alreadyexists",
                 format!("runningactivity already exists in project '{}'", item),
             ),
+            Error::RunningActivityInOtherProject(item) => (
+                "conflict",
+                format!("an activity is already running in project '{}'", item),
+            ),
+            Error::ActivityEndBeforeStart(item) => (
+                "invalid",
+                format!("activity ending at '{}' would end before it starts", item),
+            ),
+            Error::ActivityOverlaps(item) => (
+                "conflict",
+                format!("activity would overlap existing activity '{}'", item),
+            ),
+            Error::NoLongerOverlaps(item) => (
+                "conflict",
+                format!("activities '{}' no longer overlap", item),
+            ),
+            Error::ActivityAlreadyPaused(item) => {
+                ("invalid", format!("activity '{}' is already paused", item))
+            }
+            Error::ActivityNotPaused(item) => {
+                ("invalid", format!("activity '{}' is not paused", item))
+            }
+            Error::ActivityStillRunning(item) => {
+                ("conflict", format!("activity '{}' is still running", item))
+            }
+            Error::ActivitiesInDifferentProjects(item) => (
+                "invalid",
+                format!("activities '{}' belong to different projects", item),
+            ),
+            Error::ActivitiesOfDifferentTypes(item) => (
+                "invalid",
+                format!("activities '{}' have different activity types", item),
+            ),
+            Error::SplitOutsideActivity(item) => (
+                "invalid",
+                format!("split time '{}' is not within the activity", item),
+            ),
+            Error::NothingToMerge => ("invalid", "no activities given to merge".to_string()),
             Error::CountNotFound(item) => ("notfound", format!("count '{}' not found", item)),
+            Error::TagNotFound(item) => ("notfound", format!("tag '{}' not found", item)),
+            Error::InvalidTag(item) => ("invalid", format!("invalid tag '{}'", item)),
+            Error::InvalidProjectStatus(item) => {
+                ("invalid", format!("invalid project status '{}'", item))
+            }
             Error::ReportError(item) => ("report", format!("could not create report: '{}'", item)),
+            Error::SchemaTooNew(version) => (
+                "migration",
+                format!(
+                    "database schema version {} is newer than supported version {}",
+                    version,
+                    crate::migrations::SCHEMA_VERSION
+                ),
+            ),
+            Error::MigrationError(item) => (
+                "migration",
+                format!("could not upgrade database: '{}'", item),
+            ),
+            Error::HistoryError(item) => (
+                "history",
+                format!("history entry '{}' cannot be undone", item),
+            ),
+            Error::InvalidBackup(item) => (
+                "backup",
+                format!("'{}' is not a dev-tracker database", item),
+            ),
+            Error::BackupFailed(item) => ("backup", format!("backup failed: {}", item)),
+            Error::DatabaseCorrupt(item) => (
+                "integrity",
+                format!("database is damaged, restore it from a backup: {}", item),
+            ),
+            Error::CorruptRow { table, id, source } => (
+                "corrupt",
+                format!("row {} in {} cannot be read: {}", id, table, source),
+            ),
+            Error::InvalidSetting(item) => ("settings", format!("invalid setting '{}'", item)),
         };
         write!(f, "error in {}: {}", module, e)
     }
@@ -63,6 +175,7 @@ impl std::error::Error for Error {
         match self {
             Error::Rusqlite(e) => Some(e),
             Error::SerdeJson(e) => Some(e),
+            Error::CorruptRow { source, .. } => Some(source),
             _ => None,
         }
     }
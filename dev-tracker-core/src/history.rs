@@ -0,0 +1,427 @@
+use std::fmt::Display;
+
+use chrono::{DateTime, Utc};
+use rusqlite::types::{Type, Value};
+use rusqlite::{Connection, OptionalExtension, Row};
+
+use crate::model::query_rows;
+use crate::Error;
+
+// Tables that are never recorded in the history.
+const UNRECORDED: &[&str] = &["operations", "history"];
+
+/// What a change did to a row.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeAction {
+    Insert,
+    Update,
+    Delete,
+}
+
+impl Display for ChangeAction {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ChangeAction::Insert => "insert",
+            ChangeAction::Update => "update",
+            ChangeAction::Delete => "delete",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// A change to a single row, with images of the row before and after the
+/// change as JSON objects keyed by column name.
+#[derive(Debug)]
+pub struct Change {
+    pub(crate) id: u64,
+    pub(crate) table: String,
+    pub(crate) row: i64,
+    pub(crate) action: ChangeAction,
+    pub(crate) before: Option<serde_json::Value>,
+    pub(crate) after: Option<serde_json::Value>,
+}
+
+impl Change {
+    pub fn table(&self) -> &str {
+        &self.table
+    }
+
+    pub fn row(&self) -> i64 {
+        self.row
+    }
+
+    pub fn action(&self) -> ChangeAction {
+        self.action
+    }
+
+    pub fn before(&self) -> Option<&serde_json::Value> {
+        self.before.as_ref()
+    }
+
+    pub fn after(&self) -> Option<&serde_json::Value> {
+        self.after.as_ref()
+    }
+
+    /// The names of the columns whose values differ between the before and
+    /// after images.
+    pub fn changed_columns(&self) -> Vec<&str> {
+        let (Some(before), Some(after)) = (&self.before, &self.after) else {
+            return Vec::new();
+        };
+        let (Some(before), Some(after)) = (before.as_object(), after.as_object()) else {
+            return Vec::new();
+        };
+
+        after
+            .iter()
+            .filter(|(column, value)| before.get(*column) != Some(value))
+            .map(|(column, _)| column.as_str())
+            .collect()
+    }
+}
+
+/// The changes made by one use of the data store, for example one run of
+/// the command line tool.
+#[derive(Debug)]
+pub struct Operation {
+    pub(crate) id: u64,
+    pub(crate) time: DateTime<Utc>,
+    pub(crate) description: Option<String>,
+    pub(crate) undone: bool,
+    pub(crate) changes: Vec<Change>,
+}
+
+impl Operation {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn time(&self) -> DateTime<Utc> {
+        self.time
+    }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn is_undone(&self) -> bool {
+        self.undone
+    }
+
+    pub fn changes(&self) -> &[Change] {
+        &self.changes
+    }
+}
+
+impl Operation {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Operation {
+            id: row.get(0)?,
+            time: row.get(1)?,
+            description: row.get(2)?,
+            undone: row.get(3)?,
+            changes: Vec::new(),
+        })
+    }
+
+    /// Starts a new operation, later changes made through `conn` are
+    /// recorded against it.  The operation is only added to the database
+    /// when the first change is made, so uses that change nothing write
+    /// nothing.
+    pub(crate) fn begin(description: Option<&str>, conn: &Connection) -> Result<(), Error> {
+        create_triggers(conn)?;
+        set_pending(Some((Utc::now(), description.map(str::to_string))), conn)
+    }
+
+    /// The most recent operations that made changes, newest first.
+    pub(crate) fn get_latest(
+        limit: u64,
+        undone: bool,
+        conn: &Connection,
+    ) -> Result<Vec<Self>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT id, time, description, undone FROM operations
+                WHERE EXISTS (SELECT 1 FROM history WHERE history.operation = operations.id)
+                AND (?2 OR NOT undone)
+                ORDER BY id DESC LIMIT ?1",
+        )?;
+        let mut operations: Vec<_> = query_rows(
+            &mut stmt,
+            (limit, undone),
+            "operations",
+            Operation::from_row,
+        )?
+        .into_iter()
+        .collect::<Result<_, _>>()?;
+
+        for operation in operations.iter_mut() {
+            operation.changes = Change::get_with_operation(operation.id, conn)?;
+        }
+
+        Ok(operations)
+    }
+
+    /// Reverts the changes made by the operation, newest first, and marks it
+    /// as undone.  The changes made while reverting are not recorded.
+    pub(crate) fn undo(&self, conn: &Connection) -> Result<(), Error> {
+        let recorded: i64 =
+            conn.query_row("SELECT COALESCE(MAX(id), 0) FROM history", [], |row| {
+                row.get(0)
+            })?;
+
+        // Reverting must not add the pending operation, the changes it makes
+        // are removed from the history again below.
+        let pending = take_pending(conn)?;
+        for change in self.changes.iter().rev() {
+            change.revert(conn)?;
+        }
+
+        conn.execute("DELETE FROM history WHERE id > ?1", [recorded])?;
+        conn.execute("UPDATE operations SET undone = 1 WHERE id = ?1", [self.id])?;
+        set_pending(pending, conn)?;
+
+        Ok(())
+    }
+}
+
+impl Change {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let action: String = row.get(3)?;
+        let before: Option<String> = row.get(4)?;
+        let after: Option<String> = row.get(5)?;
+        Ok(Change {
+            id: row.get(0)?,
+            table: row.get(1)?,
+            row: row.get(2)?,
+            action: match action.as_str() {
+                "insert" => ChangeAction::Insert,
+                "delete" => ChangeAction::Delete,
+                _ => ChangeAction::Update,
+            },
+            before: before.map(|b| image(4, &b)).transpose()?,
+            after: after.map(|a| image(5, &a)).transpose()?,
+        })
+    }
+
+    fn get_with_operation(operation: u64, conn: &Connection) -> Result<Vec<Self>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT id, tablename, row_id, action, old_values, new_values FROM history
+                WHERE operation = ?1 ORDER BY id",
+        )?;
+        let changes: Vec<_> = query_rows(&mut stmt, [operation], "history", Change::from_row)?
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+
+        Ok(changes)
+    }
+
+    fn revert(&self, conn: &Connection) -> Result<(), Error> {
+        let table = quote(&self.table);
+        match self.action {
+            ChangeAction::Insert => {
+                conn.execute(
+                    &format!("DELETE FROM {} WHERE rowid = ?1", table),
+                    [self.row],
+                )?;
+            }
+            ChangeAction::Update => {
+                let values = self.restore_values(conn)?;
+                let assignments: Vec<_> = values
+                    .iter()
+                    .enumerate()
+                    .map(|(i, (column, _))| format!("{} = ?{}", quote(column), i + 2))
+                    .collect();
+                let mut params = vec![Value::Integer(self.row)];
+                params.extend(values.into_iter().map(|(_, value)| value));
+                conn.execute(
+                    &format!(
+                        "UPDATE {} SET {} WHERE rowid = ?1",
+                        table,
+                        assignments.join(", ")
+                    ),
+                    rusqlite::params_from_iter(params),
+                )?;
+            }
+            ChangeAction::Delete => {
+                let values = self.restore_values(conn)?;
+                let mut columns = vec!["rowid".to_string()];
+                columns.extend(values.iter().map(|(column, _)| quote(column)));
+                let placeholders: Vec<_> = (1..=columns.len()).map(|i| format!("?{}", i)).collect();
+                let mut params = vec![Value::Integer(self.row)];
+                params.extend(values.into_iter().map(|(_, value)| value));
+                conn.execute(
+                    &format!(
+                        "INSERT INTO {} ({}) VALUES ({})",
+                        table,
+                        columns.join(", "),
+                        placeholders.join(", ")
+                    ),
+                    rusqlite::params_from_iter(params),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+
+    // The values from the before image for the columns the table still has.
+    fn restore_values(&self, conn: &Connection) -> Result<Vec<(String, Value)>, Error> {
+        let Some(before) = self.before.as_ref().and_then(|b| b.as_object()) else {
+            return Err(Error::HistoryError(self.id.to_string()));
+        };
+
+        let columns = table_columns(&self.table, conn)?;
+        let values = before
+            .iter()
+            .filter(|(column, _)| columns.contains(column))
+            .map(|(column, value)| {
+                let value = match value {
+                    serde_json::Value::Null => Value::Null,
+                    serde_json::Value::Bool(b) => Value::Integer(*b as i64),
+                    serde_json::Value::Number(n) => match n.as_i64() {
+                        Some(i) => Value::Integer(i),
+                        None => Value::Real(n.as_f64().unwrap_or_default()),
+                    },
+                    serde_json::Value::String(s) => Value::Text(s.clone()),
+                    other => Value::Text(other.to_string()),
+                };
+                (column.clone(), value)
+            })
+            .collect();
+
+        Ok(values)
+    }
+}
+
+// An operation that has been started but not yet added to the database,
+// its start time and description.
+type Pending = (DateTime<Utc>, Option<String>);
+
+// Creates the triggers that record every insert, update and delete in the
+// history, and that add the pending operation before the first of them.
+// They are temporary, so they belong to the connection and only record the
+// changes made through it, never those made by other programs.  The row
+// images hold the columns each table has when the triggers are created.
+fn create_triggers(conn: &Connection) -> Result<(), Error> {
+    conn.execute_batch(
+        "CREATE TEMP TABLE IF NOT EXISTS pending_operation (
+            time        DATETIME NOT NULL,
+            description TEXT
+        );",
+    )?;
+
+    for table in recorded_tables(conn)? {
+        let columns = table_columns(&table, conn)?;
+        let image = |row: &str| {
+            let pairs: Vec<_> = columns
+                .iter()
+                .map(|c| format!("'{}', {}.{}", c, row, quote(c)))
+                .collect();
+            format!("json_object({})", pairs.join(", "))
+        };
+
+        for (action, before, after, row) in [
+            ("insert", "NULL".to_string(), image("NEW"), "NEW"),
+            ("update", image("OLD"), image("NEW"), "NEW"),
+            ("delete", image("OLD"), "NULL".to_string(), "OLD"),
+        ] {
+            conn.execute_batch(&format!(
+                "CREATE TEMP TRIGGER IF NOT EXISTS {pending} BEFORE {event} ON main.{table}
+                    WHEN EXISTS (SELECT 1 FROM pending_operation) BEGIN
+                    INSERT INTO operations (time, description)
+                        SELECT time, description FROM pending_operation;
+                    DELETE FROM pending_operation;
+                END;
+                CREATE TEMP TRIGGER IF NOT EXISTS {history} AFTER {event} ON main.{table} BEGIN
+                    INSERT INTO history (operation, tablename, row_id, action, old_values, new_values)
+                    VALUES ((SELECT MAX(id) FROM operations), '{name}', {row}.rowid, '{action}',
+                        {before}, {after});
+                END;",
+                pending = quote(&format!("pending_{}_{}", table, action)),
+                history = quote(&format!("history_{}_{}", table, action)),
+                event = action.to_uppercase(),
+                table = quote(&table),
+                name = table,
+            ))?;
+        }
+    }
+
+    Ok(())
+}
+
+fn take_pending(conn: &Connection) -> Result<Option<Pending>, Error> {
+    let pending = conn
+        .query_row(
+            "SELECT time, description FROM pending_operation",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?;
+    conn.execute("DELETE FROM pending_operation", [])?;
+
+    Ok(pending)
+}
+
+fn set_pending(pending: Option<Pending>, conn: &Connection) -> Result<(), Error> {
+    conn.execute("DELETE FROM pending_operation", [])?;
+    if let Some((time, description)) = pending {
+        conn.execute(
+            "INSERT INTO pending_operation (time, description) VALUES (?1, ?2)",
+            (time, description),
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Removes the triggers that record changes in the history, both those of
+/// the connection and any stored in the database by older versions.
+pub(crate) fn drop_triggers(conn: &Connection) -> Result<(), Error> {
+    for schema in ["main", "temp"] {
+        let triggers: Vec<String> = conn
+            .prepare(&format!(
+                "SELECT name FROM {}.sqlite_master WHERE type = 'trigger'
+                    AND (name LIKE 'history_%' OR name LIKE 'pending_%')",
+                schema
+            ))?
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<_>>()?;
+        for trigger in triggers {
+            conn.execute_batch(&format!("DROP TRIGGER {}.{};", schema, quote(&trigger)))?;
+        }
+    }
+
+    Ok(())
+}
+
+// The tables whose changes are recorded in the history.
+fn recorded_tables(conn: &Connection) -> Result<Vec<String>, Error> {
+    let tables = conn
+        .prepare(
+            "SELECT name FROM main.sqlite_master WHERE type = 'table' AND name NOT LIKE 'sqlite_%'",
+        )?
+        .query_map([], |row| row.get(0))?
+        .collect::<rusqlite::Result<Vec<String>>>()?
+        .into_iter()
+        .filter(|t| !UNRECORDED.contains(&t.as_str()))
+        .collect();
+    Ok(tables)
+}
+
+fn table_columns(table: &str, conn: &Connection) -> Result<Vec<String>, Error> {
+    let columns = conn
+        .prepare(&format!("PRAGMA table_info({})", quote(table)))?
+        .query_map([], |row| row.get(1))?
+        .collect::<rusqlite::Result<_>>()?;
+    Ok(columns)
+}
+
+// Parses a row image stored as JSON in column `index` of the history.
+fn image(index: usize, json: &str) -> rusqlite::Result<serde_json::Value> {
+    serde_json::from_str(json)
+        .map_err(|e| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e)))
+}
+
+fn quote(identifier: &str) -> String {
+    format!("\"{}\"", identifier.replace('"', "\"\""))
+}
@@ -0,0 +1,300 @@
+use std::fmt::Display;
+
+use rusqlite::types::Value;
+use rusqlite::Connection;
+use tokei::Languages;
+
+use crate::model::{Activity, Pause};
+use crate::Error;
+
+/// A kind of problem found by checking the database.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IssueKind {
+    /// SQLite found the file itself to be damaged, this cannot be fixed.
+    Corruption,
+    /// An activity whose project does not exist, fixed by deleting it.
+    OrphanedActivity,
+    /// An activity whose activity type does not exist, fixed by changing it
+    /// to the 'Unknown' activity type.
+    UnknownActivityType,
+    /// A repository whose project does not exist, fixed by deleting it.
+    OrphanedRepo,
+    /// A count whose repository does not exist, fixed by deleting it.
+    OrphanedCount,
+    /// A pause whose activity does not exist, fixed by deleting it.
+    OrphanedPause,
+    /// A project with more than one running activity, fixed by stopping each
+    /// running activity except the newest when the next one started.
+    MultipleRunning,
+    /// An activity that ends before it starts, fixed by swapping the times.
+    EndBeforeStart,
+    /// A project name used more than once, fixed by appending the ID to the
+    /// names of the newer projects.
+    DuplicateProjectName,
+    /// An activity type name used more than once, fixed by appending the ID
+    /// to the names of the newer activity types.
+    DuplicateActivityTypeName,
+    /// A repository path used more than once, fixed by moving the counts to
+    /// the oldest repository and deleting the others.
+    DuplicateRepoPath,
+    /// A count whose lines of code cannot be read, fixed by deleting it.
+    InvalidCount,
+}
+
+/// A problem found by checking the database.  `id` is the row with the
+/// problem, or for duplicates the oldest of the rows sharing the name.
+#[derive(Debug)]
+pub struct Issue {
+    pub(crate) kind: IssueKind,
+    pub(crate) id: u64,
+    pub(crate) detail: String,
+}
+
+impl Display for Issue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.kind {
+            IssueKind::Corruption => write!(f, "database is damaged: {}", self.detail),
+            IssueKind::OrphanedActivity => write!(
+                f,
+                "activity {} belongs to missing project {}",
+                self.id, self.detail
+            ),
+            IssueKind::UnknownActivityType => write!(
+                f,
+                "activity {} has missing activity type {}",
+                self.id, self.detail
+            ),
+            IssueKind::OrphanedRepo => write!(
+                f,
+                "repository {} belongs to missing project {}",
+                self.id, self.detail
+            ),
+            IssueKind::OrphanedCount => write!(
+                f,
+                "count {} belongs to missing repository {}",
+                self.id, self.detail
+            ),
+            IssueKind::OrphanedPause => write!(
+                f,
+                "pause {} belongs to missing activity {}",
+                self.id, self.detail
+            ),
+            IssueKind::MultipleRunning => write!(
+                f,
+                "project {} has {} running activities",
+                self.id, self.detail
+            ),
+            IssueKind::EndBeforeStart => {
+                write!(f, "activity {} ends before it starts", self.id)
+            }
+            IssueKind::DuplicateProjectName => {
+                write!(f, "project name '{}' is used more than once", self.detail)
+            }
+            IssueKind::DuplicateActivityTypeName => write!(
+                f,
+                "activity type name '{}' is used more than once",
+                self.detail
+            ),
+            IssueKind::DuplicateRepoPath => write!(
+                f,
+                "repository path '{}' is used more than once",
+                self.detail
+            ),
+            IssueKind::InvalidCount => {
+                write!(f, "count {} cannot be read: {}", self.id, self.detail)
+            }
+        }
+    }
+}
+
+impl Issue {
+    pub fn kind(&self) -> IssueKind {
+        self.kind
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn detail(&self) -> &str {
+        &self.detail
+    }
+
+    pub fn is_fixable(&self) -> bool {
+        self.kind != IssueKind::Corruption
+    }
+}
+
+impl Issue {
+    pub(crate) fn find_all(conn: &Connection) -> Result<Vec<Self>, Error> {
+        let mut issues = Vec::new();
+
+        let mut stmt = conn.prepare("PRAGMA integrity_check")?;
+        let results: Vec<String> = stmt
+            .query_map([], |row| row.get(0))?
+            .collect::<rusqlite::Result<Vec<String>>>()?
+            .into_iter()
+            .filter(|r| r != "ok")
+            .collect();
+        for result in results {
+            issues.push(Issue {
+                kind: IssueKind::Corruption,
+                id: 0,
+                detail: result,
+            });
+        }
+
+        for (kind, sql) in [
+            (
+                IssueKind::OrphanedActivity,
+                "SELECT id, project FROM activities
+                    WHERE project NOT IN (SELECT id FROM projects)",
+            ),
+            (
+                IssueKind::UnknownActivityType,
+                "SELECT id, atype FROM activities
+                    WHERE atype NOT IN (SELECT id FROM activitytypes)",
+            ),
+            (
+                IssueKind::OrphanedRepo,
+                "SELECT id, project FROM repos WHERE project NOT IN (SELECT id FROM projects)",
+            ),
+            (
+                IssueKind::OrphanedCount,
+                "SELECT id, repo FROM counts WHERE repo NOT IN (SELECT id FROM repos)",
+            ),
+            (
+                IssueKind::OrphanedPause,
+                "SELECT id, activity FROM pauses WHERE activity NOT IN (SELECT id FROM activities)",
+            ),
+            (
+                IssueKind::MultipleRunning,
+                "SELECT project, COUNT(*) FROM activities WHERE end IS NULL
+                    GROUP BY project HAVING COUNT(*) > 1",
+            ),
+            (
+                IssueKind::EndBeforeStart,
+                "SELECT id, end FROM activities
+                    WHERE end IS NOT NULL AND julianday(end) < julianday(start)",
+            ),
+            (
+                IssueKind::DuplicateProjectName,
+                "SELECT MIN(id), name FROM projects GROUP BY name HAVING COUNT(*) > 1",
+            ),
+            (
+                IssueKind::DuplicateActivityTypeName,
+                "SELECT MIN(id), name FROM activitytypes GROUP BY name HAVING COUNT(*) > 1",
+            ),
+            (
+                IssueKind::DuplicateRepoPath,
+                "SELECT MIN(id), path FROM repos GROUP BY path HAVING COUNT(*) > 1",
+            ),
+        ] {
+            let mut stmt = conn.prepare(sql)?;
+            let found: Vec<Issue> = stmt
+                .query_map([], |row| {
+                    let detail: Value = row.get(1)?;
+                    Ok(Issue {
+                        kind,
+                        id: row.get(0)?,
+                        detail: match detail {
+                            Value::Integer(i) => i.to_string(),
+                            Value::Text(t) => t,
+                            other => format!("{:?}", other),
+                        },
+                    })
+                })?
+                .collect::<rusqlite::Result<_>>()?;
+            issues.extend(found);
+        }
+
+        let mut stmt = conn.prepare("SELECT id, count FROM counts")?;
+        let counts: Vec<(u64, Value)> = stmt
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+        for (id, json) in counts {
+            let problem = match json {
+                Value::Text(json) => serde_json::from_str::<Languages>(&json)
+                    .err()
+                    .map(|e| e.to_string()),
+                _ => Some("not text".to_string()),
+            };
+            if let Some(detail) = problem {
+                issues.push(Issue {
+                    kind: IssueKind::InvalidCount,
+                    id,
+                    detail,
+                });
+            }
+        }
+
+        Ok(issues)
+    }
+
+    pub(crate) fn fix(&self, conn: &Connection) -> Result<(), Error> {
+        match self.kind {
+            IssueKind::Corruption => {
+                return Err(Error::DatabaseCorrupt(self.detail.clone()));
+            }
+            IssueKind::OrphanedActivity => {
+                conn.execute("DELETE FROM activities WHERE id = ?1", [self.id])?;
+            }
+            IssueKind::UnknownActivityType => {
+                conn.execute("UPDATE activities SET atype = 0 WHERE id = ?1", [self.id])?;
+            }
+            IssueKind::OrphanedRepo => {
+                conn.execute("DELETE FROM repos WHERE id = ?1", [self.id])?;
+            }
+            IssueKind::OrphanedCount | IssueKind::InvalidCount => {
+                conn.execute("DELETE FROM counts WHERE id = ?1", [self.id])?;
+            }
+            IssueKind::OrphanedPause => {
+                conn.execute("DELETE FROM pauses WHERE id = ?1", [self.id])?;
+            }
+            IssueKind::MultipleRunning => {
+                let mut running = Activity::get_running_with_project(self.id, conn)?;
+                running.sort_by_key(|a| a.start);
+                for i in 1..running.len() {
+                    let end = running[i].start;
+                    let activity = &mut running[i - 1];
+                    activity.end = Some(end);
+                    activity.update(conn)?;
+                    Pause::trim_with_activity(activity.id, end, conn)?;
+                }
+            }
+            IssueKind::EndBeforeStart => {
+                conn.execute(
+                    "UPDATE activities SET start = end, end = start WHERE id = ?1",
+                    [self.id],
+                )?;
+            }
+            IssueKind::DuplicateProjectName => {
+                conn.execute(
+                    "UPDATE projects SET name = name || ' (' || id || ')'
+                        WHERE name = ?1 AND id != ?2",
+                    (&self.detail, self.id),
+                )?;
+            }
+            IssueKind::DuplicateActivityTypeName => {
+                conn.execute(
+                    "UPDATE activitytypes SET name = name || ' (' || id || ')'
+                        WHERE name = ?1 AND id != ?2",
+                    (&self.detail, self.id),
+                )?;
+            }
+            IssueKind::DuplicateRepoPath => {
+                conn.execute(
+                    "UPDATE counts SET repo = ?2
+                        WHERE repo IN (SELECT id FROM repos WHERE path = ?1 AND id != ?2)",
+                    (&self.detail, self.id),
+                )?;
+                conn.execute(
+                    "DELETE FROM repos WHERE path = ?1 AND id != ?2",
+                    (&self.detail, self.id),
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,6 +1,12 @@
 pub mod data;
+pub mod history;
+pub mod integrity;
+pub mod migrations;
 pub mod model;
+pub mod overlap;
+pub mod query;
 pub mod report;
+pub mod settings;
 
 pub mod errors;
 pub use errors::*;
@@ -0,0 +1,376 @@
+use rusqlite::{Connection, Transaction};
+
+use crate::history;
+use crate::Error;
+
+type Migration = fn(&Transaction) -> Result<(), Error>;
+
+// Each entry upgrades the schema by one version, the version number of a
+// database is the number of migrations that have been applied to it.  Never
+// edit or reorder an existing entry, only append new ones.
+const MIGRATIONS: &[Migration] = &[
+    v1_initial_tables,
+    v2_foreign_keys,
+    v3_indexes,
+    v4_pauses,
+    v5_settings,
+    v6_tags,
+    v7_project_metadata,
+    v8_project_parents,
+    v9_archive,
+    v10_history,
+    v11_start_index,
+    v12_archived_status,
+    v13_connection_history_triggers,
+];
+
+pub const SCHEMA_VERSION: u32 = MIGRATIONS.len() as u32;
+
+pub(crate) fn schema_version(conn: &Connection) -> Result<u32, Error> {
+    let version = conn.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    Ok(version)
+}
+
+pub(crate) fn migrate(conn: &Connection) -> Result<(), Error> {
+    let version = schema_version(conn)?;
+    if version > SCHEMA_VERSION {
+        return Err(Error::SchemaTooNew(version));
+    }
+
+    if version == SCHEMA_VERSION {
+        return Ok(());
+    }
+
+    // Foreign keys can only be toggled outside of a transaction and must be
+    // off while tables are rebuilt, otherwise dropping the old tables would
+    // cascade into the new ones.  They are turned back on whether or not the
+    // upgrade succeeds.
+    conn.pragma_update(None, "foreign_keys", false)?;
+    let upgraded = upgrade(conn, version);
+    conn.pragma_update(None, "foreign_keys", true)?;
+
+    upgraded
+}
+
+// Applies the migrations after `version` in one transaction, so a failure
+// part way through leaves the file as it was.
+fn upgrade(conn: &Connection, version: u32) -> Result<(), Error> {
+    let tx = conn.unchecked_transaction()?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        migration(&tx)?;
+        check_foreign_keys(&tx)?;
+        tx.pragma_update(None, "user_version", index as u32 + 1)?;
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+fn check_foreign_keys(tx: &Transaction) -> Result<(), Error> {
+    let mut stmt = tx.prepare("PRAGMA foreign_key_check")?;
+    if let Some(row) = stmt.query([])?.next()? {
+        let table: String = row.get(0)?;
+        let id: i64 = row.get(1)?;
+        return Err(Error::MigrationError(format!(
+            "row {} in table {} violates a foreign key constraint",
+            id, table
+        )));
+    }
+
+    Ok(())
+}
+
+// Databases created before schema versioning report a version of 0 but may
+// already contain these tables, so everything here must be idempotent.
+fn v1_initial_tables(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE IF NOT EXISTS projects (
+            id          INTEGER PRIMARY KEY,
+            name        TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS activities (
+            id          INTEGER PRIMARY KEY,
+            project     INTEGER NOT NULL,
+            atype       INTEGER NOT NULL,
+            description TEXT,
+            start       DATETIME NOT NULL,
+            end         DATETIME
+        );
+        CREATE TABLE IF NOT EXISTS activitytypes (
+            id          INTEGER PRIMARY KEY,
+            name        TEXT NOT NULL,
+            description TEXT
+        );
+        CREATE TABLE IF NOT EXISTS repos (
+            id          INTEGER PRIMARY KEY,
+            project     INTEGER NOT NULL,
+            path        TEXT NOT NULL
+        );
+        CREATE TABLE IF NOT EXISTS counts (
+            id      INTEGER PRIMARY KEY,
+            repo    INTEGER NOT NULL,
+            date    DATETIME NOT NULL,
+            count   TEXT NOT NULL
+        );
+        INSERT OR IGNORE INTO activitytypes (id, name, description)
+            VALUES (0, 'Unknown', 'Unknown activity type');",
+    )?;
+    Ok(())
+}
+
+// Rebuilds every table with foreign key and uniqueness constraints.  Rows that
+// could never be reached through the DataStore (activities and repos of deleted
+// projects, counts of deleted repos) are removed, activities with a deleted
+// activity type are reassigned to 'Unknown', duplicate project and activity
+// type names get their id appended, and the counts of duplicate repos are moved
+// to the oldest repo with the same path.
+fn v2_foreign_keys(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "UPDATE projects SET name = name || ' (' || id || ')'
+            WHERE id NOT IN (SELECT MIN(id) FROM projects GROUP BY name);
+        UPDATE activitytypes SET name = name || ' (' || id || ')'
+            WHERE id NOT IN (SELECT MIN(id) FROM activitytypes GROUP BY name);
+        UPDATE counts SET repo = (
+                SELECT MIN(r2.id) FROM repos r1 JOIN repos r2 ON r1.path = r2.path
+                WHERE r1.id = counts.repo
+            )
+            WHERE repo IN (SELECT id FROM repos);
+        DELETE FROM repos WHERE id NOT IN (SELECT MIN(id) FROM repos GROUP BY path);
+
+        DELETE FROM activities WHERE project NOT IN (SELECT id FROM projects);
+        UPDATE activities SET atype = 0 WHERE atype NOT IN (SELECT id FROM activitytypes);
+        DELETE FROM repos WHERE project NOT IN (SELECT id FROM projects);
+        DELETE FROM counts WHERE repo NOT IN (SELECT id FROM repos);
+
+        CREATE TABLE projects_new (
+            id          INTEGER PRIMARY KEY,
+            name        TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE activitytypes_new (
+            id          INTEGER PRIMARY KEY,
+            name        TEXT NOT NULL UNIQUE,
+            description TEXT
+        );
+        CREATE TABLE activities_new (
+            id          INTEGER PRIMARY KEY,
+            project     INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+            atype       INTEGER NOT NULL DEFAULT 0
+                        REFERENCES activitytypes(id) ON DELETE SET DEFAULT,
+            description TEXT,
+            start       DATETIME NOT NULL,
+            end         DATETIME
+        );
+        CREATE TABLE repos_new (
+            id          INTEGER PRIMARY KEY,
+            project     INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+            path        TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE counts_new (
+            id      INTEGER PRIMARY KEY,
+            repo    INTEGER NOT NULL REFERENCES repos(id) ON DELETE CASCADE,
+            date    DATETIME NOT NULL,
+            count   TEXT NOT NULL
+        );
+
+        INSERT INTO projects_new (id, name) SELECT id, name FROM projects;
+        INSERT INTO activitytypes_new (id, name, description)
+            SELECT id, name, description FROM activitytypes;
+        INSERT INTO activities_new (id, project, atype, description, start, end)
+            SELECT id, project, atype, description, start, end FROM activities;
+        INSERT INTO repos_new (id, project, path) SELECT id, project, path FROM repos;
+        INSERT INTO counts_new (id, repo, date, count) SELECT id, repo, date, count FROM counts;
+
+        DROP TABLE counts;
+        DROP TABLE repos;
+        DROP TABLE activities;
+        DROP TABLE activitytypes;
+        DROP TABLE projects;
+
+        ALTER TABLE projects_new RENAME TO projects;
+        ALTER TABLE activitytypes_new RENAME TO activitytypes;
+        ALTER TABLE activities_new RENAME TO activities;
+        ALTER TABLE repos_new RENAME TO repos;
+        ALTER TABLE counts_new RENAME TO counts;",
+    )?;
+    Ok(())
+}
+
+// Supports the per-project, per-repo and date range queries used when listing
+// and reporting, and the foreign key checks made when deleting.
+fn v3_indexes(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE INDEX activities_project_start ON activities (project, start);
+        CREATE INDEX activities_running ON activities (project) WHERE end IS NULL;
+        CREATE INDEX activities_atype ON activities (atype);
+        CREATE INDEX repos_project ON repos (project);
+        CREATE INDEX counts_repo_date ON counts (repo, date);",
+    )?;
+    Ok(())
+}
+
+// Periods when a running activity was paused, for example for a lunch break,
+// which are not counted in the duration of the activity.  A pause with no end
+// is still in progress.
+fn v4_pauses(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE pauses (
+            id          INTEGER PRIMARY KEY,
+            activity    INTEGER NOT NULL REFERENCES activities(id) ON DELETE CASCADE,
+            start       DATETIME NOT NULL,
+            end         DATETIME
+        );
+        CREATE INDEX pauses_activity ON pauses (activity, end);",
+    )?;
+    Ok(())
+}
+
+// Per-database settings such as the running activity policy, stored as text
+// and parsed by the settings module.
+fn v5_settings(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE settings (
+            name        TEXT PRIMARY KEY,
+            value       TEXT NOT NULL
+        );",
+    )?;
+    Ok(())
+}
+
+// Free-form tags, attached to any number of activities and projects.
+fn v6_tags(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE tags (
+            id          INTEGER PRIMARY KEY,
+            name        TEXT NOT NULL UNIQUE
+        );
+        CREATE TABLE activity_tags (
+            activity    INTEGER NOT NULL REFERENCES activities(id) ON DELETE CASCADE,
+            tag         INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (activity, tag)
+        );
+        CREATE TABLE project_tags (
+            project     INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+            tag         INTEGER NOT NULL REFERENCES tags(id) ON DELETE CASCADE,
+            PRIMARY KEY (project, tag)
+        );
+        CREATE INDEX activity_tags_tag ON activity_tags (tag);
+        CREATE INDEX project_tags_tag ON project_tags (tag);",
+    )?;
+    Ok(())
+}
+
+// Descriptive details for projects.  Existing projects are taken to have been
+// created when their first activity started, or now if they have none.
+fn v7_project_metadata(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE projects ADD COLUMN description TEXT;
+        ALTER TABLE projects ADD COLUMN status TEXT NOT NULL DEFAULT 'active';
+        ALTER TABLE projects ADD COLUMN created DATETIME;
+        ALTER TABLE projects ADD COLUMN target DATE;
+        ALTER TABLE projects ADD COLUMN url TEXT;
+        UPDATE projects SET created = COALESCE(
+            (SELECT MIN(start) FROM activities WHERE activities.project = projects.id),
+            strftime('%Y-%m-%d %H:%M:%f+00:00', 'now')
+        );",
+    )?;
+    Ok(())
+}
+
+// Projects can be sub-projects of another project.  Deleting a parent leaves
+// its children as top level projects.
+fn v8_project_parents(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE projects ADD COLUMN parent INTEGER
+            REFERENCES projects(id) ON DELETE SET NULL;
+        CREATE INDEX projects_parent ON projects (parent);",
+    )?;
+    Ok(())
+}
+
+// Activity types and repos can be archived rather than deleted, projects use
+// their status for this.
+fn v9_archive(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "ALTER TABLE activitytypes ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;
+        ALTER TABLE repos ADD COLUMN archived INTEGER NOT NULL DEFAULT 0;",
+    )?;
+    Ok(())
+}
+
+// A log of every change made through the DataStore, grouped into operations,
+// so that mistakes can be undone.  The triggers that fill in the history are
+// created from the current columns of each table once migrations are done.
+fn v10_history(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch(
+        "CREATE TABLE operations (
+            id          INTEGER PRIMARY KEY,
+            time        DATETIME NOT NULL,
+            description TEXT,
+            undone      INTEGER NOT NULL DEFAULT 0
+        );
+        CREATE TABLE history (
+            id          INTEGER PRIMARY KEY,
+            operation   INTEGER REFERENCES operations(id) ON DELETE CASCADE,
+            tablename   TEXT NOT NULL,
+            row_id      INTEGER NOT NULL,
+            action      TEXT NOT NULL,
+            old_values  TEXT,
+            new_values  TEXT
+        );
+        CREATE INDEX history_operation ON history (operation);",
+    )?;
+    Ok(())
+}
+
+// Overlaps are found across projects by joining activities on their start
+// times, which needs an index on the start alone.
+fn v11_start_index(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch("CREATE INDEX activities_start ON activities (start);")?;
+    Ok(())
+}
+
+// Projects remember the status they had when they were archived so that
+// unarchiving them can put it back.
+fn v12_archived_status(tx: &Transaction) -> Result<(), Error> {
+    tx.execute_batch("ALTER TABLE projects ADD COLUMN archived_status TEXT;")?;
+    Ok(())
+}
+
+// The history triggers were stored in the file, so they also recorded
+// changes made by other programs.  They are now created for each connection
+// by the data store instead.
+fn v13_connection_history_triggers(tx: &Transaction) -> Result<(), Error> {
+    history::drop_triggers(tx)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn foreign_keys(conn: &Connection) -> bool {
+        conn.pragma_query_value(None, "foreign_keys", |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn foreign_keys_are_on_after_failed_upgrade() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "foreign_keys", true).unwrap();
+        // A table the pauses migration would create already exists.
+        conn.execute_batch("CREATE TABLE pauses (id INTEGER PRIMARY KEY);")
+            .unwrap();
+
+        assert!(migrate(&conn).is_err());
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+        assert!(foreign_keys(&conn));
+    }
+
+    #[test]
+    fn foreign_keys_are_on_after_upgrade() {
+        let conn = Connection::open_in_memory().unwrap();
+
+        migrate(&conn).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), SCHEMA_VERSION);
+        assert!(foreign_keys(&conn));
+    }
+}
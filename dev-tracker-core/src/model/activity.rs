@@ -1,32 +1,30 @@
 use std::fmt::Display;
 
 use chrono::{DateTime, Duration, Utc};
-use rusqlite::Connection;
+use rusqlite::{Connection, Row, ToSql};
+use serde::{Serialize, Serializer};
 
+use super::query_rows;
+use crate::query::{ActivityOrder, ActivityQuery};
 use crate::Error;
 
-pub(crate) fn init_table(conn: &Connection) -> Result<(), crate::Error> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS activities (
-            id          INTEGER PRIMARY KEY,
-            project     INTEGER NOT NULL,
-            atype       INTEGER NOT NULL,
-            description TEXT,
-            start       DATETIME NOT NULL,
-            end         DATETIME
-        )",
-        (),
-    )?;
-    Ok(())
-}
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct Activity {
     pub(crate) id: u64,
     pub(crate) project: u64,
+    #[serde(rename = "activity_type")]
     pub(crate) atype: u64,
     pub(crate) description: Option<String>,
     pub(crate) start: DateTime<Utc>,
     pub(crate) end: Option<DateTime<Utc>>,
+    #[serde(rename = "paused_minutes", serialize_with = "minutes")]
+    pub(crate) paused: Duration,
+    pub(crate) paused_since: Option<DateTime<Utc>>,
+}
+
+// Durations are written as whole minutes, as they are in reports.
+fn minutes<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_i64(duration.num_minutes())
 }
 
 impl Display for Activity {
@@ -63,6 +61,27 @@ impl Activity {
             description,
             start: Utc::now(),
             end: None,
+            paused: Duration::zero(),
+            paused_since: None,
+        }
+    }
+
+    pub fn with_times(
+        project: u64,
+        atype: u64,
+        description: Option<String>,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            id: 0,
+            project,
+            atype,
+            description,
+            start,
+            end,
+            paused: Duration::zero(),
+            paused_since: None,
         }
     }
 
@@ -98,6 +117,10 @@ impl Activity {
         self.start
     }
 
+    pub fn set_start_time(&mut self, start: DateTime<Utc>) {
+        self.start = start;
+    }
+
     pub fn end_time(&self) -> Option<DateTime<Utc>> {
         self.end
     }
@@ -112,88 +135,244 @@ impl Activity {
     }
 
     pub fn duration(&self) -> Option<Duration> {
-        let Some(end) = self.end else {
-            return None;
-        };
+        let end = self.end?;
 
-        Some(end - self.start)
+        Some(self.worked_until(end))
+    }
+
+    /// The time spent on the activity from its start until `time`, not
+    /// counting any time it was paused.
+    pub fn worked_until(&self, time: DateTime<Utc>) -> Duration {
+        time - self.start - self.paused_until(time)
+    }
+
+    /// The total time the activity was paused up until `time`, including the
+    /// current pause if the activity is paused.
+    pub fn paused_until(&self, time: DateTime<Utc>) -> Duration {
+        match self.paused_since {
+            Some(since) if time > since => self.paused + (time - since),
+            _ => self.paused,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused_since.is_some()
+    }
+
+    /// When the current pause started, if the activity is paused.
+    pub fn paused_since(&self) -> Option<DateTime<Utc>> {
+        self.paused_since
     }
 }
 
+// The activity columns followed by the total milliseconds of finished pauses
+// and the start of the current pause, for a query on `activities a`.
+const COLUMNS: &str = "a.id, a.project, a.atype, a.description, a.start, a.end,
+    (SELECT CAST(COALESCE(SUM(ROUND((julianday(p.end) - julianday(p.start)) * 86400000)), 0)
+        AS INTEGER) FROM pauses p WHERE p.activity = a.id AND p.end IS NOT NULL) AS paused,
+    (SELECT p.start FROM pauses p WHERE p.activity = a.id AND p.end IS NULL) AS paused_since";
+
 impl Activity {
-    pub(crate) fn create(&self, conn: &Connection) -> Result<(), Error> {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Activity {
+            id: row.get(0)?,
+            project: row.get(1)?,
+            atype: row.get(2)?,
+            description: row.get(3)?,
+            start: row.get(4)?,
+            end: row.get(5)?,
+            paused: Duration::milliseconds(row.get(6)?),
+            paused_since: row.get(7)?,
+        })
+    }
+
+    pub(crate) fn create(&mut self, conn: &Connection) -> Result<(), Error> {
         conn.execute(
-            "INSERT INTO activities (project, atype, description, start) VALUES (?1, ?2, ?3, ?4)",
-            (&self.project, &self.atype, &self.description, &self.start),
+            "INSERT INTO activities (project, atype, description, start, end) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (&self.project, &self.atype, &self.description, &self.start, &self.end),
         )?;
+        self.id = conn.last_insert_rowid() as u64;
         Ok(())
     }
 
     pub(crate) fn get_with_id(id: u64, conn: &Connection) -> Result<Option<Self>, Error> {
-        let mut stmt = conn.prepare(
-            "SELECT id, project, atype, description, start, end FROM activities WHERE id=?1",
-        )?;
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM activities a WHERE a.id=?1",
+            COLUMNS
+        ))?;
 
-        let mut activities: Vec<_> = stmt
-            .query_map([id], |row| {
-                Ok(Activity {
-                    id: row.get(0)?,
-                    project: row.get(1)?,
-                    atype: row.get(2)?,
-                    description: row.get(3)?,
-                    start: row.get(4)?,
-                    end: row.get(5)?,
-                })
-            })?
-            .filter_map(|a| a.ok())
-            .collect();
+        let mut activities = query_rows(&mut stmt, [id], "activities", Activity::from_row)?;
 
         if activities.len() == 1 {
-            Ok(Some(activities.remove(0)))
+            Ok(Some(activities.remove(0)?))
         } else {
             Ok(None)
         }
     }
 
-    pub(crate) fn get_if_running(conn: &Connection) -> Result<Vec<Self>, Error> {
+    pub(crate) fn get_running_with_project(
+        project: u64,
+        conn: &Connection,
+    ) -> Result<Vec<Self>, Error> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM activities a WHERE a.project=?1 AND a.end IS NULL",
+            COLUMNS
+        ))?;
+
+        let activities: Vec<_> =
+            query_rows(&mut stmt, [project], "activities", Activity::from_row)?
+                .into_iter()
+                .collect::<Result<_, _>>()?;
+
+        Ok(activities)
+    }
+
+    // The ids of every pair of activities whose time spans overlap, where
+    // either activity is in `project` if one is given, ordered by the start of
+    // the first.  The first activity starts no later than the second, and a
+    // running activity is treated as ending at `now`.
+    pub(crate) fn get_overlapping_pairs(
+        project: Option<u64>,
+        now: DateTime<Utc>,
+        conn: &Connection,
+    ) -> Result<Vec<(u64, u64)>, Error> {
         let mut stmt = conn.prepare(
-            "SELECT id, project, atype, description, start, end FROM activities WHERE end IS NULL",
+            "SELECT a.id, b.id FROM activities a JOIN activities b
+                ON (b.start > a.start OR (b.start = a.start AND b.id > a.id))
+                AND b.start < COALESCE(a.end, ?1)
+                WHERE ?2 IS NULL OR a.project = ?2 OR b.project = ?2
+                ORDER BY a.start, a.id, b.start, b.id",
         )?;
+        let pairs = stmt
+            .query_map((now, project), |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<rusqlite::Result<_>>()?;
+
+        Ok(pairs)
+    }
+
+    // Activities in `project` other than `exclude` whose time span overlaps
+    // `start` to `end`, where a missing end means the activity is running.
+    pub(crate) fn get_overlapping(
+        project: u64,
+        start: DateTime<Utc>,
+        end: Option<DateTime<Utc>>,
+        exclude: u64,
+        conn: &Connection,
+    ) -> Result<Vec<Self>, Error> {
+        let mut sql = format!(
+            "SELECT {} FROM activities a
+                WHERE a.project=? AND a.id<>? AND (a.end IS NULL OR a.end > ?)",
+            COLUMNS
+        );
+        let mut params: Vec<&dyn ToSql> = vec![&project, &exclude, &start];
+        if let Some(end) = end.as_ref() {
+            sql.push_str(" AND a.start < ?");
+            params.push(end);
+        }
 
-        let activities: Vec<_> = stmt
-            .query_map([], |row| {
-                Ok(Activity {
-                    id: row.get(0)?,
-                    project: row.get(1)?,
-                    atype: row.get(2)?,
-                    description: row.get(3)?,
-                    start: row.get(4)?,
-                    end: row.get(5)?,
-                })
-            })?
-            .filter_map(|a| a.ok())
-            .collect();
+        let mut stmt = conn.prepare(&sql)?;
+        let activities: Vec<_> =
+            query_rows(&mut stmt, &params[..], "activities", Activity::from_row)?
+                .into_iter()
+                .collect::<Result<_, _>>()?;
 
         Ok(activities)
     }
 
-    pub(crate) fn get_all(conn: &Connection) -> Result<Vec<Self>, Error> {
-        let mut stmt =
-            conn.prepare("SELECT id, project, atype, description, start, end FROM activities")?;
-
-        let activities: Vec<_> = stmt
-            .query_map([], |row| {
-                Ok(Activity {
-                    id: row.get(0)?,
-                    project: row.get(1)?,
-                    atype: row.get(2)?,
-                    description: row.get(3)?,
-                    start: row.get(4)?,
-                    end: row.get(5)?,
-                })
-            })?
-            .filter_map(|a| a.ok())
-            .collect();
+    pub(crate) fn query(
+        query: &ActivityQuery,
+        conn: &Connection,
+    ) -> Result<Vec<Result<Self, Error>>, Error> {
+        let mut sql = format!(
+            "SELECT {} FROM activities a JOIN activitytypes t ON a.atype = t.id WHERE 1=1",
+            COLUMNS
+        );
+        let mut params: Vec<&dyn ToSql> = Vec::new();
+
+        if !query.projects.is_empty() {
+            sql.push_str(&format!(
+                " AND a.project IN ({})",
+                placeholders(query.projects.len())
+            ));
+            params.extend(query.projects.iter().map(|p| p as &dyn ToSql));
+        }
+        if !query.atypes.is_empty() {
+            sql.push_str(&format!(
+                " AND a.atype IN ({})",
+                placeholders(query.atypes.len())
+            ));
+            params.extend(query.atypes.iter().map(|at| at as &dyn ToSql));
+        }
+        if !query.tags.is_empty() {
+            let tags = placeholders(query.tags.len());
+            sql.push_str(&format!(
+                " AND (a.id IN (SELECT at.activity FROM activity_tags at
+                        JOIN tags g ON at.tag = g.id WHERE g.name IN ({}))
+                    OR a.project IN (SELECT pt.project FROM project_tags pt
+                        JOIN tags g ON pt.tag = g.id WHERE g.name IN ({})))",
+                tags, tags
+            ));
+            params.extend(query.tags.iter().map(|t| t as &dyn ToSql));
+            params.extend(query.tags.iter().map(|t| t as &dyn ToSql));
+        }
+        if let Some(since) = query.since.as_ref() {
+            sql.push_str(" AND a.start >= ?");
+            params.push(since);
+        }
+        if let Some(until) = query.until.as_ref() {
+            sql.push_str(" AND a.start <= ?");
+            params.push(until);
+        }
+        match query.running {
+            Some(true) => sql.push_str(" AND a.end IS NULL"),
+            Some(false) => sql.push_str(" AND a.end IS NOT NULL"),
+            None => {}
+        }
+        let pattern = query.description.as_ref().map(|text| {
+            let escaped = text
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            format!("%{}%", escaped)
+        });
+        if let Some(pattern) = pattern.as_ref() {
+            sql.push_str(" AND a.description LIKE ? ESCAPE '\\'");
+            params.push(pattern);
+        }
+
+        let order = match query.order {
+            ActivityOrder::Start => "a.start",
+            ActivityOrder::Id => "a.id",
+            ActivityOrder::Duration => {
+                "(julianday(COALESCE(a.end, 'now')) - julianday(a.start)) * 86400000 - paused"
+            }
+            ActivityOrder::ActivityType => "t.name",
+        };
+        let direction = if query.reverse { "DESC" } else { "ASC" };
+        sql.push_str(&format!(
+            " ORDER BY {} {}, a.id {}",
+            order, direction, direction
+        ));
+
+        match (query.limit.as_ref(), query.offset.as_ref()) {
+            (Some(limit), Some(offset)) => {
+                sql.push_str(" LIMIT ? OFFSET ?");
+                params.push(limit);
+                params.push(offset);
+            }
+            (Some(limit), None) => {
+                sql.push_str(" LIMIT ?");
+                params.push(limit);
+            }
+            (None, Some(offset)) => {
+                sql.push_str(" LIMIT -1 OFFSET ?");
+                params.push(offset);
+            }
+            (None, None) => {}
+        }
+
+        let mut stmt = conn.prepare(&sql)?;
+        let activities = query_rows(&mut stmt, &params[..], "activities", Activity::from_row)?;
 
         Ok(activities)
     }
@@ -208,10 +387,11 @@ impl Activity {
     }
 
     pub(crate) fn delete(self, conn: &Connection) -> Result<(), Error> {
-        conn.execute(
-            "DELETE FROM activities WHERE id=?1",
-            [&self.id.to_string()],
-        )?;
+        conn.execute("DELETE FROM activities WHERE id=?1", [&self.id.to_string()])?;
         Ok(())
     }
 }
+
+fn placeholders(n: usize) -> String {
+    vec!["?"; n].join(", ")
+}
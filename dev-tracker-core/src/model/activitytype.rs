@@ -1,33 +1,17 @@
 use std::fmt::Display;
 
-use rusqlite::Connection;
+use rusqlite::{Connection, Row};
+use serde::Serialize;
 
+use super::query_rows;
 use crate::Error;
 
-pub(crate) fn init_table(conn: &Connection) -> Result<(), crate::Error> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS activitytypes (
-            id          INTEGER PRIMARY KEY,
-            name        TEXT NOT NULL,
-            description TEXT
-        )",
-        (),
-    )?;
-
-    if ActivityType::get_with_id(0, conn)?.is_none() {
-        conn.execute(
-        "INSERT INTO activitytypes (id, name, description) VALUES (0, 'Unknown', 'Unknown activity type')",
-    ())?;
-    }
-
-    Ok(())
-}
-
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct ActivityType {
     pub(crate) id: u64,
     pub(crate) name: String,
     pub(crate) description: Option<String>,
+    pub(crate) archived: bool,
 }
 
 impl Display for ActivityType {
@@ -42,6 +26,7 @@ impl ActivityType {
             id: 0,
             name,
             description,
+            archived: false,
         }
     }
 
@@ -64,9 +49,24 @@ impl ActivityType {
     pub fn set_description(&mut self, description: Option<String>) {
         self.description = description;
     }
+
+    /// Archived activity types are hidden from listings and cannot be used
+    /// for new activities, but are kept for existing ones.
+    pub fn is_archived(&self) -> bool {
+        self.archived
+    }
 }
 
 impl ActivityType {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(ActivityType {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            archived: row.get(3)?,
+        })
+    }
+
     pub(crate) fn create(&self, conn: &Connection) -> Result<(), Error> {
         conn.execute(
             "INSERT INTO activitytypes (name, description) VALUES (?1, ?2)",
@@ -77,62 +77,37 @@ impl ActivityType {
 
     pub(crate) fn get_with_id(id: u64, conn: &Connection) -> Result<Option<Self>, Error> {
         let mut stmt =
-            conn.prepare("SELECT id, name, description FROM activitytypes WHERE id=?1")?;
-        let mut ats: Vec<ActivityType> = stmt
-            .query_map([&id], |row| {
-                Ok(ActivityType {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    description: row.get(1)?,
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+            conn.prepare("SELECT id, name, description, archived FROM activitytypes WHERE id=?1")?;
+        let mut ats = query_rows(&mut stmt, [&id], "activitytypes", ActivityType::from_row)?;
 
         if ats.len() == 1 {
-            Ok(Some(ats.remove(0)))
+            Ok(Some(ats.remove(0)?))
         } else {
             Ok(None)
         }
     }
 
     pub(crate) fn get_with_name(name: &str, conn: &Connection) -> Result<Vec<Self>, Error> {
-        let mut stmt =
-            conn.prepare("SELECT id, name, description FROM activitytypes WHERE name=?1")?;
-        let ats: Vec<ActivityType> = stmt
-            .query_map([&name], |row| {
-                Ok(ActivityType {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    description: row.get(1)?,
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+        let mut stmt = conn
+            .prepare("SELECT id, name, description, archived FROM activitytypes WHERE name=?1")?;
+        let ats: Vec<_> = query_rows(&mut stmt, [&name], "activitytypes", ActivityType::from_row)?
+            .into_iter()
+            .collect::<Result<_, _>>()?;
 
         Ok(ats)
     }
 
-    pub(crate) fn get_all(conn: &Connection) -> Result<Vec<Self>, Error> {
-        let mut stmt = conn.prepare("SELECT id, name, description FROM activitytypes")?;
-        let ats: Vec<ActivityType> = stmt
-            .query_map([], |row| {
-                Ok(ActivityType {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                    description: row.get(1)?,
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+    pub(crate) fn get_all(conn: &Connection) -> Result<Vec<Result<Self, Error>>, Error> {
+        let mut stmt = conn.prepare("SELECT id, name, description, archived FROM activitytypes")?;
+        let ats = query_rows(&mut stmt, [], "activitytypes", ActivityType::from_row)?;
 
         Ok(ats)
     }
 
     pub(crate) fn update(&self, conn: &Connection) -> Result<(), Error> {
         conn.execute(
-            "UPDATE activitytypes SET name=?2 WHERE id=?1",
-            (&self.id, &self.name),
+            "UPDATE activitytypes SET name=?2, description=?3, archived=?4 WHERE id=?1",
+            (&self.id, &self.name, &self.description, &self.archived),
         )?;
 
         Ok(())
@@ -1,32 +1,29 @@
 use std::fmt::Display;
 
 use chrono::{DateTime, Utc};
-use rusqlite::Connection;
+use rusqlite::types::Type;
+use rusqlite::{Connection, Row, ToSql};
+use serde::{Serialize, Serializer};
 use tokei::Languages;
 
+use super::query_rows;
 use crate::Error;
 
-pub(crate) fn init_table(conn: &Connection) -> Result<(), Error> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS counts (
-            id      INTEGER PRIMARY KEY,
-            repo    INTEGER NOT NULL,
-            date    DATETIME NOT NULL,
-            count   TEXT NOT NULL
-        )",
-        (),
-    )?;
-    Ok(())
-}
-
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Count {
     pub(crate) id: u64,
     pub(crate) repo: u64,
     pub(crate) date: DateTime<Utc>,
+    #[serde(rename = "lines_of_code", serialize_with = "total_code")]
     pub(crate) count: Languages,
 }
 
+// Only the total lines of code are written, the counts for each language are
+// in reports.
+fn total_code<S: Serializer>(count: &Languages, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_u64(count.total().code as u64)
+}
+
 impl Display for Count {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
@@ -68,6 +65,18 @@ impl Count {
 }
 
 impl Count {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let json: String = row.get(3)?;
+        let count = serde_json::from_str(&json)
+            .map_err(|e| rusqlite::Error::FromSqlConversionFailure(3, Type::Text, Box::new(e)))?;
+        Ok(Count {
+            id: row.get(0)?,
+            repo: row.get(1)?,
+            date: row.get(2)?,
+            count,
+        })
+    }
+
     pub(crate) fn create(&self, conn: &Connection) -> Result<(), Error> {
         let json = serde_json::to_string(&self.count)?;
         conn.execute(
@@ -79,46 +88,55 @@ impl Count {
 
     pub(crate) fn get_with_id(id: u64, conn: &Connection) -> Result<Option<Self>, Error> {
         let mut stmt = conn.prepare("SELECT id, repo, date, count FROM counts WHERE id=?1")?;
-        let mut counts: Vec<Count> = stmt
-            .query_map([&id], |row| {
-                let json: String = row.get(3)?;
-                let count = serde_json::from_str(&json).expect("should always get valud JSON");
-                Ok(Count {
-                    id: row.get(0)?,
-                    repo: row.get(1)?,
-                    date: row.get(2)?,
-                    count,
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+        let mut counts = query_rows(&mut stmt, [&id], "counts", Count::from_row)?;
 
         if counts.len() == 1 {
-            Ok(Some(counts.remove(0)))
+            Ok(Some(counts.remove(0)?))
         } else {
             Ok(None)
         }
     }
 
-    pub(crate) fn get_all(conn: &Connection) -> Result<Vec<Self>, Error> {
-        let mut stmt = conn.prepare("SELECT id, repo, date, count FROM counts")?;
-        let counts: Vec<Count> = stmt
-            .query_map([], |row| {
-                let json: String = row.get(3)?;
-                let count = serde_json::from_str(&json).expect("should always get valud JSON");
-                Ok(Count {
-                    id: row.get(0)?,
-                    repo: row.get(1)?,
-                    date: row.get(2)?,
-                    count,
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+    pub(crate) fn get_with_repo(
+        repo: u64,
+        start: Option<DateTime<Utc>>,
+        end: Option<DateTime<Utc>>,
+        conn: &Connection,
+    ) -> Result<Vec<Result<Self, Error>>, Error> {
+        let mut sql = String::from("SELECT id, repo, date, count FROM counts WHERE repo=?");
+        let mut params: Vec<&dyn ToSql> = vec![&repo];
+        if let Some(start) = start.as_ref() {
+            sql.push_str(" AND date >= ?");
+            params.push(start);
+        }
+        if let Some(end) = end.as_ref() {
+            sql.push_str(" AND date <= ?");
+            params.push(end);
+        }
+        sql.push_str(" ORDER BY date");
+
+        let mut stmt = conn.prepare(&sql)?;
+        let counts = query_rows(&mut stmt, &params[..], "counts", Count::from_row)?;
 
         Ok(counts)
     }
 
+    pub(crate) fn get_latest_with_repo(
+        repo: u64,
+        conn: &Connection,
+    ) -> Result<Option<Self>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT id, repo, date, count FROM counts WHERE repo=?1 ORDER BY date DESC LIMIT 1",
+        )?;
+        let mut counts = query_rows(&mut stmt, [&repo], "counts", Count::from_row)?;
+
+        if counts.len() == 1 {
+            Ok(Some(counts.remove(0)?))
+        } else {
+            Ok(None)
+        }
+    }
+
     pub(crate) fn delete(self, conn: &Connection) -> Result<(), Error> {
         conn.execute("DELETE FROM counts WHERE id=?1", [&self.id])?;
         Ok(())
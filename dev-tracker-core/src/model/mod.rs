@@ -7,8 +7,41 @@ pub use activitytype::*;
 pub mod count;
 pub use count::*;
 
+pub mod pause;
+pub use pause::*;
+
 pub mod project;
 pub use project::*;
 
 pub mod repo;
 pub use repo::*;
+
+pub mod tag;
+pub use tag::*;
+
+use rusqlite::{Params, Row, Statement};
+
+use crate::Error;
+
+// Runs a query whose first column is the row ID, returning each row or, when
+// a row cannot be read, an `Error::CorruptRow` in its place so that callers
+// can decide whether to skip it or give up.
+pub(crate) fn query_rows<T, P: Params>(
+    stmt: &mut Statement,
+    params: P,
+    table: &str,
+    from_row: fn(&Row) -> rusqlite::Result<T>,
+) -> Result<Vec<Result<T, Error>>, Error> {
+    let rows = stmt
+        .query_map(params, |row| {
+            let id = row.get(0)?;
+            Ok(from_row(row).map_err(|source| Error::CorruptRow {
+                table: table.to_string(),
+                id,
+                source,
+            }))
+        })?
+        .collect::<rusqlite::Result<_>>()?;
+
+    Ok(rows)
+}
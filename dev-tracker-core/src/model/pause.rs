@@ -0,0 +1,131 @@
+use chrono::{DateTime, Utc};
+use rusqlite::{Connection, Row};
+
+use super::query_rows;
+use crate::Error;
+
+#[derive(Clone, Debug)]
+pub struct Pause {
+    pub(crate) id: u64,
+    pub(crate) activity: u64,
+    pub(crate) start: DateTime<Utc>,
+    pub(crate) end: Option<DateTime<Utc>>,
+}
+
+impl Pause {
+    pub fn new(activity: u64, start: DateTime<Utc>) -> Self {
+        Self {
+            id: 0,
+            activity,
+            start,
+            end: None,
+        }
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn activity(&self) -> u64 {
+        self.activity
+    }
+
+    pub fn start_time(&self) -> DateTime<Utc> {
+        self.start
+    }
+
+    pub fn end_time(&self) -> Option<DateTime<Utc>> {
+        self.end
+    }
+}
+
+impl Pause {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Pause {
+            id: row.get(0)?,
+            activity: row.get(1)?,
+            start: row.get(2)?,
+            end: row.get(3)?,
+        })
+    }
+
+    pub(crate) fn create(&mut self, conn: &Connection) -> Result<(), Error> {
+        conn.execute(
+            "INSERT INTO pauses (activity, start, end) VALUES (?1, ?2, ?3)",
+            (&self.activity, &self.start, &self.end),
+        )?;
+        self.id = conn.last_insert_rowid() as u64;
+        Ok(())
+    }
+
+    pub(crate) fn get_with_activity(activity: u64, conn: &Connection) -> Result<Vec<Self>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT id, activity, start, end FROM pauses WHERE activity=?1 ORDER BY start",
+        )?;
+
+        let pauses: Vec<_> = query_rows(&mut stmt, [activity], "pauses", Pause::from_row)?
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+
+        Ok(pauses)
+    }
+
+    pub(crate) fn get_running_with_activity(
+        activity: u64,
+        conn: &Connection,
+    ) -> Result<Option<Self>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT id, activity, start, end FROM pauses WHERE activity=?1 AND end IS NULL",
+        )?;
+
+        let mut pauses = query_rows(&mut stmt, [activity], "pauses", Pause::from_row)?;
+
+        if pauses.len() == 1 {
+            Ok(Some(pauses.remove(0)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub(crate) fn update(&self, conn: &Connection) -> Result<(), Error> {
+        conn.execute(
+            "UPDATE pauses SET start=?2, end=?3 WHERE id=?1",
+            (&self.id, &self.start, &self.end),
+        )?;
+
+        Ok(())
+    }
+
+    // Removes the pauses of `activity` that start at or after `end` and ends
+    // any that carry on past it, used when an activity is shortened.
+    pub(crate) fn trim_with_activity(
+        activity: u64,
+        end: DateTime<Utc>,
+        conn: &Connection,
+    ) -> Result<(), Error> {
+        conn.execute(
+            "DELETE FROM pauses WHERE activity=?1 AND start >= ?2",
+            (&activity, &end),
+        )?;
+        conn.execute(
+            "UPDATE pauses SET end=?2 WHERE activity=?1 AND (end IS NULL OR end > ?2)",
+            (&activity, &end),
+        )?;
+        Ok(())
+    }
+
+    // Moves the pauses of activity `from` that start at or after `start` to
+    // activity `to`.
+    pub(crate) fn move_to_activity(
+        from: u64,
+        to: u64,
+        start: DateTime<Utc>,
+        conn: &Connection,
+    ) -> Result<(), Error> {
+        conn.execute(
+            "UPDATE pauses SET activity=?2 WHERE activity=?1 AND start >= ?3",
+            (&from, &to, &start),
+        )?;
+        Ok(())
+    }
+}
@@ -1,24 +1,69 @@
 use std::fmt::Display;
+use std::str::FromStr;
 
-use rusqlite::Connection;
+use chrono::{DateTime, NaiveDate, Utc};
+use rusqlite::types::Type;
+use rusqlite::{Connection, Row};
+use serde::Serialize;
 
+use super::query_rows;
 use crate::Error;
 
-pub(crate) fn init_table(conn: &Connection) -> Result<(), Error> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS projects (
-            id          INTEGER PRIMARY KEY,
-            name        TEXT NOT NULL
-        )",
-        (),
-    )?;
-    Ok(())
+const COLUMNS: &str =
+    "id, name, description, status, created, target, url, parent, archived_status";
+
+/// Where a project is in its life.  Archived projects are hidden when listing
+/// projects unless asked for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectStatus {
+    #[default]
+    Active,
+    Paused,
+    Finished,
+    Archived,
+}
+
+impl Display for ProjectStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            ProjectStatus::Active => "active",
+            ProjectStatus::Paused => "paused",
+            ProjectStatus::Finished => "finished",
+            ProjectStatus::Archived => "archived",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for ProjectStatus {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "active" => Ok(ProjectStatus::Active),
+            "paused" => Ok(ProjectStatus::Paused),
+            "finished" => Ok(ProjectStatus::Finished),
+            "archived" => Ok(ProjectStatus::Archived),
+            _ => Err(Error::InvalidProjectStatus(s.to_string())),
+        }
+    }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Project {
     pub(crate) id: u64,
     pub(crate) name: String,
+    pub(crate) description: Option<String>,
+    pub(crate) status: ProjectStatus,
+    pub(crate) created: DateTime<Utc>,
+    pub(crate) target: Option<NaiveDate>,
+    pub(crate) url: Option<String>,
+    pub(crate) parent: Option<u64>,
+    // The status the project had before it was archived, put back when it
+    // is unarchived.
+    #[serde(skip)]
+    pub(crate) archived_status: Option<ProjectStatus>,
 }
 
 impl Display for Project {
@@ -29,7 +74,17 @@ impl Display for Project {
 
 impl Project {
     pub fn new(name: String) -> Self {
-        Self { id: 0, name }
+        Self {
+            id: 0,
+            name,
+            description: None,
+            status: ProjectStatus::Active,
+            created: Utc::now(),
+            target: None,
+            url: None,
+            parent: None,
+            archived_status: None,
+        }
     }
 
     pub fn id(&self) -> u64 {
@@ -43,67 +98,184 @@ impl Project {
     pub fn set_name(&mut self, name: String) {
         self.name = name;
     }
+
+    pub fn description(&self) -> Option<&str> {
+        self.description.as_deref()
+    }
+
+    pub fn set_description(&mut self, description: Option<String>) {
+        self.description = description;
+    }
+
+    pub fn status(&self) -> ProjectStatus {
+        self.status
+    }
+
+    pub fn set_status(&mut self, status: ProjectStatus) {
+        if status != ProjectStatus::Archived {
+            self.archived_status = None;
+        } else if self.status != ProjectStatus::Archived {
+            self.archived_status = Some(self.status);
+        }
+        self.status = status;
+    }
+
+    pub fn created(&self) -> DateTime<Utc> {
+        self.created
+    }
+
+    pub fn set_created(&mut self, created: DateTime<Utc>) {
+        self.created = created;
+    }
+
+    /// The date by which the project is meant to be finished.
+    pub fn target(&self) -> Option<NaiveDate> {
+        self.target
+    }
+
+    pub fn set_target(&mut self, target: Option<NaiveDate>) {
+        self.target = target;
+    }
+
+    /// The homepage or issue tracker for the project.
+    pub fn url(&self) -> Option<&str> {
+        self.url.as_deref()
+    }
+
+    pub fn set_url(&mut self, url: Option<String>) {
+        self.url = url;
+    }
+
+    /// The ID of the project this is a sub-project of.
+    pub fn parent(&self) -> Option<u64> {
+        self.parent
+    }
+
+    pub fn set_parent(&mut self, parent: Option<&Project>) {
+        self.parent = parent.map(|p| p.id);
+    }
 }
 
 impl Project {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let parse_status = |index: usize, status: String| {
+            status.parse().map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(e))
+            })
+        };
+        let status = parse_status(3, row.get(3)?)?;
+        let archived_status = row
+            .get::<_, Option<String>>(8)?
+            .map(|s| parse_status(8, s))
+            .transpose()?;
+        Ok(Project {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            description: row.get(2)?,
+            status,
+            created: row.get(4)?,
+            target: row.get(5)?,
+            url: row.get(6)?,
+            parent: row.get(7)?,
+            archived_status,
+        })
+    }
+
     pub(crate) fn create(&self, conn: &Connection) -> Result<(), Error> {
-        conn.execute("INSERT INTO projects (name) VALUES (?1)", [&self.name])?;
+        conn.execute(
+            "INSERT INTO projects
+                (name, description, status, created, target, url, parent, archived_status)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+            (
+                &self.name,
+                &self.description,
+                self.status.to_string(),
+                &self.created,
+                &self.target,
+                &self.url,
+                &self.parent,
+                self.archived_status.map(|s| s.to_string()),
+            ),
+        )?;
         Ok(())
     }
 
     pub(crate) fn get_with_id(id: u64, conn: &Connection) -> Result<Option<Self>, Error> {
-        let mut stmt = conn.prepare("SELECT id, name FROM projects WHERE id=?1")?;
-        let mut projects: Vec<Project> = stmt
-            .query_map([&id], |row| {
-                Ok(Project {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM projects WHERE id=?1", COLUMNS))?;
+        let mut projects = query_rows(&mut stmt, [&id], "projects", Project::from_row)?;
 
         if projects.len() == 1 {
-            Ok(Some(projects.remove(0)))
+            Ok(Some(projects.remove(0)?))
         } else {
             Ok(None)
         }
     }
 
     pub(crate) fn get_with_name(name: &str, conn: &Connection) -> Result<Vec<Self>, Error> {
-        let mut stmt = conn.prepare("SELECT id, name FROM projects WHERE name=?1")?;
-        let projects: Vec<Project> = stmt
-            .query_map([&name], |row| {
-                Ok(Project {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM projects WHERE name=?1", COLUMNS))?;
+        let projects: Vec<_> = query_rows(&mut stmt, [&name], "projects", Project::from_row)?
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+
+        Ok(projects)
+    }
+
+    pub(crate) fn get_all(conn: &Connection) -> Result<Vec<Result<Self, Error>>, Error> {
+        let mut stmt = conn.prepare(&format!("SELECT {} FROM projects", COLUMNS))?;
+        let projects = query_rows(&mut stmt, [], "projects", Project::from_row)?;
+
+        Ok(projects)
+    }
+
+    /// The direct sub-projects of the project with `id`.
+    pub(crate) fn get_with_parent(
+        id: u64,
+        conn: &Connection,
+    ) -> Result<Vec<Result<Self, Error>>, Error> {
+        let mut stmt =
+            conn.prepare(&format!("SELECT {} FROM projects WHERE parent=?1", COLUMNS))?;
+        let projects = query_rows(&mut stmt, [&id], "projects", Project::from_row)?;
 
         Ok(projects)
     }
 
-    pub(crate) fn get_all(conn: &Connection) -> Result<Vec<Self>, Error> {
-        let mut stmt = conn.prepare("SELECT id, name FROM projects")?;
-        let projects: Vec<Project> = stmt
-            .query_map([], |row| {
-                Ok(Project {
-                    id: row.get(0)?,
-                    name: row.get(1)?,
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+    /// All of the sub-projects below the project with `id`, however deeply
+    /// nested.
+    pub(crate) fn get_descendants(
+        id: u64,
+        conn: &Connection,
+    ) -> Result<Vec<Result<Self, Error>>, Error> {
+        let mut stmt = conn.prepare(&format!(
+            "WITH RECURSIVE tree(id) AS (
+                SELECT id FROM projects WHERE parent=?1
+                UNION
+                SELECT p.id FROM projects p JOIN tree t ON p.parent = t.id
+            )
+            SELECT {} FROM projects WHERE id IN tree",
+            COLUMNS
+        ))?;
+        let projects = query_rows(&mut stmt, [&id], "projects", Project::from_row)?;
 
         Ok(projects)
     }
 
     pub(crate) fn update(&self, conn: &Connection) -> Result<(), Error> {
         conn.execute(
-            "UPDATE projects SET name=?2 WHERE id=?1",
-            (&self.id, &self.name),
+            "UPDATE projects
+                SET name=?2, description=?3, status=?4, created=?5, target=?6, url=?7, parent=?8,
+                    archived_status=?9
+                WHERE id=?1",
+            (
+                &self.id,
+                &self.name,
+                &self.description,
+                self.status.to_string(),
+                &self.created,
+                &self.target,
+                &self.url,
+                &self.parent,
+                self.archived_status.map(|s| s.to_string()),
+            ),
         )?;
 
         Ok(())
@@ -3,27 +3,18 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use rusqlite::Connection;
+use rusqlite::{Connection, Row};
+use serde::Serialize;
 
+use super::query_rows;
 use crate::Error;
 
-pub(crate) fn init_table(conn: &Connection) -> Result<(), crate::Error> {
-    conn.execute(
-        "CREATE TABLE IF NOT EXISTS repos (
-            id          INTEGER PRIMARY KEY,
-            project     INTEGER NOT NULL,
-            path        TEXT NOT NULL
-        )",
-        (),
-    )?;
-    Ok(())
-}
-
-#[derive(Debug)]
+#[derive(Debug, Serialize)]
 pub struct Repo {
     pub(crate) id: u64,
     pub(crate) project: u64,
     pub(crate) path: PathBuf,
+    pub(crate) archived: bool,
 }
 
 impl Display for Repo {
@@ -38,6 +29,7 @@ impl Repo {
             id: 0,
             project,
             path,
+            archived: false,
         }
     }
 
@@ -60,9 +52,25 @@ impl Repo {
     pub fn set_path(&mut self, path: PathBuf) {
         self.path = path;
     }
+
+    /// Archived repositories are hidden from listings and are not counted,
+    /// but their existing counts are kept.
+    pub fn is_archived(&self) -> bool {
+        self.archived
+    }
 }
 
 impl Repo {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        let path: String = row.get(2)?;
+        Ok(Repo {
+            id: row.get(0)?,
+            project: row.get(1)?,
+            path: PathBuf::from(path),
+            archived: row.get(3)?,
+        })
+    }
+
     pub(crate) fn create(&self, conn: &Connection) -> Result<(), Error> {
         conn.execute(
             "INSERT INTO repos (project, path) VALUES (?1, ?2)",
@@ -72,64 +80,51 @@ impl Repo {
     }
 
     pub(crate) fn get_with_id(id: u64, conn: &Connection) -> Result<Option<Self>, Error> {
-        let mut stmt = conn.prepare("SELECT id, project, path FROM repos WHERE id=?1")?;
-        let mut repos: Vec<Repo> = stmt
-            .query_map([&id], |row| {
-                let path: String = row.get(2)?;
-                Ok(Repo {
-                    id: row.get(0)?,
-                    project: row.get(1)?,
-                    path: PathBuf::from(path),
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+        let mut stmt = conn.prepare("SELECT id, project, path, archived FROM repos WHERE id=?1")?;
+        let mut repos = query_rows(&mut stmt, [&id], "repos", Repo::from_row)?;
 
         if repos.len() == 1 {
-            Ok(Some(repos.remove(0)))
+            Ok(Some(repos.remove(0)?))
         } else {
             Ok(None)
         }
     }
 
     pub(crate) fn get_with_path(path: &Path, conn: &Connection) -> Result<Vec<Self>, Error> {
-        let mut stmt = conn.prepare("SELECT id, project, path FROM repos WHERE path=?1")?;
-        let repos: Vec<Repo> = stmt
-            .query_map([&path.display().to_string()], |row| {
-                let path: String = row.get(2)?;
-                Ok(Repo {
-                    id: row.get(0)?,
-                    project: row.get(1)?,
-                    path: PathBuf::from(path),
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+        let mut stmt =
+            conn.prepare("SELECT id, project, path, archived FROM repos WHERE path=?1")?;
+        let repos: Vec<_> = query_rows(
+            &mut stmt,
+            [&path.display().to_string()],
+            "repos",
+            Repo::from_row,
+        )?
+        .into_iter()
+        .collect::<Result<_, _>>()?;
 
         Ok(repos)
     }
 
-    pub(crate) fn get_all(conn: &Connection) -> Result<Vec<Self>, Error> {
-        let mut stmt = conn.prepare("SELECT id, project, path FROM repos")?;
-        let repos: Vec<Repo> = stmt
-            .query_map([], |row| {
-                let path: String = row.get(2)?;
-                Ok(Repo {
-                    id: row.get(0)?,
-                    project: row.get(1)?,
-                    path: PathBuf::from(path),
-                })
-            })?
-            .filter_map(|p| p.ok())
-            .collect();
+    pub(crate) fn get_with_project(
+        project: u64,
+        conn: &Connection,
+    ) -> Result<Vec<Result<Self, Error>>, Error> {
+        let mut stmt =
+            conn.prepare("SELECT id, project, path, archived FROM repos WHERE project=?1")?;
+        let repos = query_rows(&mut stmt, [&project], "repos", Repo::from_row)?;
 
         Ok(repos)
     }
 
     pub(crate) fn update(&self, conn: &Connection) -> Result<(), Error> {
         conn.execute(
-            "UPDATE repos SET project=?2, path=?3 WHERE id=?1",
-            (&self.id, &self.project, &self.path.display().to_string()),
+            "UPDATE repos SET project=?2, path=?3, archived=?4 WHERE id=?1",
+            (
+                &self.id,
+                &self.project,
+                &self.path.display().to_string(),
+                &self.archived,
+            ),
         )?;
 
         Ok(())
@@ -0,0 +1,164 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use rusqlite::{Connection, OptionalExtension, Row};
+use serde::Serialize;
+
+use super::query_rows;
+use crate::Error;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Tag {
+    pub(crate) id: u64,
+    pub(crate) name: String,
+}
+
+impl Display for Tag {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.id, self.name)
+    }
+}
+
+impl Tag {
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+impl Tag {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(Tag {
+            id: row.get(0)?,
+            name: row.get(1)?,
+        })
+    }
+
+    // Tags are created the first time they are used.
+    pub(crate) fn get_or_create(name: &str, conn: &Connection) -> Result<Self, Error> {
+        conn.execute("INSERT OR IGNORE INTO tags (name) VALUES (?1)", [name])?;
+        let tag = conn.query_row(
+            "SELECT id, name FROM tags WHERE name=?1",
+            [name],
+            Tag::from_row,
+        )?;
+        Ok(tag)
+    }
+
+    pub(crate) fn get_with_name(name: &str, conn: &Connection) -> Result<Option<Self>, Error> {
+        let tag = conn
+            .query_row(
+                "SELECT id, name FROM tags WHERE name=?1",
+                [name],
+                Tag::from_row,
+            )
+            .optional()?;
+        Ok(tag)
+    }
+
+    pub(crate) fn get_all(conn: &Connection) -> Result<Vec<Result<Self, Error>>, Error> {
+        let mut stmt = conn.prepare("SELECT id, name FROM tags ORDER BY name")?;
+        let tags = query_rows(&mut stmt, [], "tags", Tag::from_row)?;
+
+        Ok(tags)
+    }
+
+    pub(crate) fn get_with_activity(activity: u64, conn: &Connection) -> Result<Vec<Self>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.name FROM tags t JOIN activity_tags at ON at.tag = t.id
+                WHERE at.activity=?1 ORDER BY t.name",
+        )?;
+        let tags: Vec<_> = query_rows(&mut stmt, [activity], "tags", Tag::from_row)?
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+
+        Ok(tags)
+    }
+
+    /// The tags of each of the activities with `ids`, found with one query
+    /// however many there are.  Activities without tags are left out.
+    pub(crate) fn get_with_activities(
+        ids: &[u64],
+        conn: &Connection,
+    ) -> Result<HashMap<u64, Vec<Self>>, Error> {
+        // The IDs are passed as one JSON array, so there is no limit on how
+        // many there can be.
+        let ids = serde_json::to_string(ids)?;
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.name, at.activity FROM tags t JOIN activity_tags at ON at.tag = t.id
+                WHERE at.activity IN (SELECT value FROM json_each(?1)) ORDER BY t.name",
+        )?;
+        let rows = query_rows(&mut stmt, [ids], "tags", |row| {
+            Ok((row.get::<_, u64>(2)?, Tag::from_row(row)?))
+        })?;
+
+        let mut tags: HashMap<u64, Vec<Self>> = HashMap::new();
+        for row in rows {
+            let (activity, tag) = row?;
+            tags.entry(activity).or_default().push(tag);
+        }
+
+        Ok(tags)
+    }
+
+    pub(crate) fn get_with_project(project: u64, conn: &Connection) -> Result<Vec<Self>, Error> {
+        let mut stmt = conn.prepare(
+            "SELECT t.id, t.name FROM tags t JOIN project_tags pt ON pt.tag = t.id
+                WHERE pt.project=?1 ORDER BY t.name",
+        )?;
+        let tags: Vec<_> = query_rows(&mut stmt, [project], "tags", Tag::from_row)?
+            .into_iter()
+            .collect::<Result<_, _>>()?;
+
+        Ok(tags)
+    }
+
+    pub(crate) fn add_to_activity(&self, activity: u64, conn: &Connection) -> Result<(), Error> {
+        conn.execute(
+            "INSERT OR IGNORE INTO activity_tags (activity, tag) VALUES (?1, ?2)",
+            (&activity, &self.id),
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn remove_from_activity(
+        &self,
+        activity: u64,
+        conn: &Connection,
+    ) -> Result<(), Error> {
+        conn.execute(
+            "DELETE FROM activity_tags WHERE activity=?1 AND tag=?2",
+            (&activity, &self.id),
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn add_to_project(&self, project: u64, conn: &Connection) -> Result<(), Error> {
+        conn.execute(
+            "INSERT OR IGNORE INTO project_tags (project, tag) VALUES (?1, ?2)",
+            (&project, &self.id),
+        )?;
+        Ok(())
+    }
+
+    pub(crate) fn remove_from_project(&self, project: u64, conn: &Connection) -> Result<(), Error> {
+        conn.execute(
+            "DELETE FROM project_tags WHERE project=?1 AND tag=?2",
+            (&project, &self.id),
+        )?;
+        Ok(())
+    }
+
+    // Removes tags that are no longer attached to anything.
+    pub(crate) fn delete_unused(conn: &Connection) -> Result<(), Error> {
+        conn.execute(
+            "DELETE FROM tags WHERE id NOT IN (SELECT tag FROM activity_tags)
+                AND id NOT IN (SELECT tag FROM project_tags)",
+            [],
+        )?;
+        Ok(())
+    }
+}
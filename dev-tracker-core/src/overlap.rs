@@ -0,0 +1,55 @@
+use chrono::{DateTime, Duration, Utc};
+
+use crate::model::Activity;
+
+/// Two activities whose time spans overlap, `first` starts no later than
+/// `second`.  Running activities are treated as ending at the time the
+/// overlap was found.
+#[derive(Clone, Debug)]
+pub struct Overlap {
+    pub(crate) first: Activity,
+    pub(crate) second: Activity,
+    pub(crate) found: DateTime<Utc>,
+}
+
+impl Overlap {
+    pub fn first(&self) -> &Activity {
+        &self.first
+    }
+
+    pub fn second(&self) -> &Activity {
+        &self.second
+    }
+
+    /// Whether both activities belong to the same project.
+    pub fn same_project(&self) -> bool {
+        self.first.project == self.second.project
+    }
+
+    /// Whether both activities have the same activity type.
+    pub fn same_activity_type(&self) -> bool {
+        self.first.atype == self.second.atype
+    }
+
+    /// How long the two activities overlap for.
+    pub fn duration(&self) -> Duration {
+        let first_end = self.first.end.unwrap_or(self.found);
+        let second_end = self.second.end.unwrap_or(self.found);
+        first_end.min(second_end) - self.second.start
+    }
+}
+
+/// How to remove an overlap between two activities.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum OverlapResolution {
+    /// End the first activity when the second one starts.
+    Trim,
+    /// Combine the two activities, with their tags and descriptions, into
+    /// the first one, only possible when both activities belong to the same
+    /// project and have the same activity type.
+    Merge,
+    /// Cut the second activity out of the first one, if the first activity
+    /// carries on after the second has finished the remainder becomes a new
+    /// activity.
+    Split,
+}
@@ -0,0 +1,102 @@
+use chrono::{DateTime, Utc};
+
+use crate::model::{ActivityType, Project};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ActivityOrder {
+    #[default]
+    Start,
+    Id,
+    Duration,
+    ActivityType,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ActivityQuery {
+    pub(crate) projects: Vec<u64>,
+    pub(crate) atypes: Vec<u64>,
+    pub(crate) tags: Vec<String>,
+    pub(crate) since: Option<DateTime<Utc>>,
+    pub(crate) until: Option<DateTime<Utc>>,
+    pub(crate) running: Option<bool>,
+    pub(crate) description: Option<String>,
+    pub(crate) order: ActivityOrder,
+    pub(crate) reverse: bool,
+    pub(crate) limit: Option<u64>,
+    pub(crate) offset: Option<u64>,
+}
+
+impl ActivityQuery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match activities for this project, can be called more than once
+    /// to match activities for any of several projects.
+    pub fn project(mut self, project: &Project) -> Self {
+        self.projects.push(project.id);
+        self
+    }
+
+    /// Only match activities of this type, can be called more than once to
+    /// match activities of any of several types.
+    pub fn activitytype(mut self, at: &ActivityType) -> Self {
+        self.atypes.push(at.id);
+        self
+    }
+
+    /// Only match activities tagged with `tag`, either directly or through
+    /// their project, can be called more than once to match activities with
+    /// any of several tags.
+    pub fn tag(mut self, tag: &str) -> Self {
+        self.tags.push(tag.to_string());
+        self
+    }
+
+    /// Only match activities that started at or after `since`.
+    pub fn since(mut self, since: DateTime<Utc>) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Only match activities that started at or before `until`.
+    pub fn until(mut self, until: DateTime<Utc>) -> Self {
+        self.until = Some(until);
+        self
+    }
+
+    /// Only match activities that are still running (`true`) or that have
+    /// finished (`false`).
+    pub fn running(mut self, running: bool) -> Self {
+        self.running = Some(running);
+        self
+    }
+
+    /// Only match activities whose description contains `text`, ignoring
+    /// ASCII case.
+    pub fn description(mut self, text: &str) -> Self {
+        self.description = Some(text.to_string());
+        self
+    }
+
+    pub fn order_by(mut self, order: ActivityOrder) -> Self {
+        self.order = order;
+        self
+    }
+
+    /// Reverse the ordering, for example to list the most recent first.
+    pub fn reverse(mut self, reverse: bool) -> Self {
+        self.reverse = reverse;
+        self
+    }
+
+    pub fn limit(mut self, limit: u64) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    pub fn offset(mut self, offset: u64) -> Self {
+        self.offset = Some(offset);
+        self
+    }
+}
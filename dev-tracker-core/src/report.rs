@@ -1,6 +1,7 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
 
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
 use tokei::Languages;
 
@@ -16,6 +17,9 @@ pub struct Report {
 
     pub(crate) activities: Vec<Activity>,
     pub(crate) counts: HashMap<String, Vec<Count>>,
+    // Reports generated before tags were added have no tags.
+    #[serde(default)]
+    pub(crate) tags: BTreeMap<String, i64>,
 }
 
 impl Report {
@@ -28,28 +32,36 @@ impl Report {
             start,
             end,
             project_name: project.name.clone(),
-            project_description: None,
+            project_description: project.description.clone(),
             activities: Vec::new(),
             counts: HashMap::new(),
+            tags: BTreeMap::new(),
         }
     }
 
-    pub fn print(&self) {
+    /// Prints the report with dates shown in the timezone `tz`.
+    pub fn print<Tz: TimeZone>(&self, tz: &Tz)
+    where
+        Tz::Offset: Display,
+    {
+        let date =
+            |datetime: DateTime<Utc>| datetime.with_timezone(tz).format("%A %d %B %Y").to_string();
+
         let start_phrase = match self.start {
-            Some(start) => start.format("%A %d %B %Y").to_string(),
-            None => match self.activities.get(0) {
-                Some(activity) => activity.start.format("%A %d %B %Y").to_string(),
+            Some(start) => date(start),
+            None => match self.activities.first() {
+                Some(activity) => date(activity.start),
                 None => "beginning".to_string(),
             },
         };
-        let end_phrase = match self.end {
-            Some(end) => end.format("%A %d %B %Y").to_string(),
-            None => Utc::now().format("%A %d %B %Y").to_string(),
-        };
+        let end_phrase = date(self.end.unwrap_or_else(Utc::now));
         println!(
             "Report for {} covering period from {} to {}.",
             self.project_name, start_phrase, end_phrase
         );
+        if let Some(description) = &self.project_description {
+            println!("{}", description);
+        }
 
         if self.activities.is_empty() {
             println!("\n  There were no activities recorded.");
@@ -66,11 +78,18 @@ impl Report {
                     "    {} for {} on {}.",
                     activity.name,
                     minutes_to_str(activity.minutes),
-                    activity.start.format("%A %d %B %Y")
+                    date(activity.start)
                 );
             }
         }
 
+        if !self.tags.is_empty() {
+            println!("\n  Time by tag:");
+            for (tag, minutes) in &self.tags {
+                println!("    {} for {}.", tag, minutes_to_str(*minutes));
+            }
+        }
+
         if !self.counts.is_empty() {
             let loc: usize = self
                 .counts
@@ -103,7 +122,7 @@ fn minutes_to_str(minutes: i64) -> String {
     let minutes = minutes % 60;
 
     let hours = if hours == 1 {
-        format!("1 hour ")
+        "1 hour ".to_string()
     } else if hours > 1 {
         format!("{} hours ", hours)
     } else {
@@ -111,7 +130,7 @@ fn minutes_to_str(minutes: i64) -> String {
     };
 
     let minutes = if minutes == 1 {
-        format!("1 minute")
+        "1 minute".to_string()
     } else if minutes > 1 {
         format!("{} minutes", minutes)
     } else {
@@ -126,6 +145,8 @@ pub struct Activity {
     pub(crate) name: String,
     pub(crate) start: DateTime<Utc>,
     pub(crate) minutes: i64,
+    #[serde(default)]
+    pub(crate) tags: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
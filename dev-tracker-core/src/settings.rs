@@ -0,0 +1,62 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::Error;
+
+pub(crate) const RUNNING_POLICY: &str = "running_policy";
+
+/// Whether activities may run in more than one project at the same time.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RunningPolicy {
+    /// Each project may have its own running activity.
+    #[default]
+    PerProject,
+    /// Refuse to start an activity while another project has one running.
+    Refuse,
+    /// Stop the running activities in other projects when an activity starts.
+    AutoStop,
+}
+
+impl Display for RunningPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            RunningPolicy::PerProject => "per-project",
+            RunningPolicy::Refuse => "refuse",
+            RunningPolicy::AutoStop => "auto-stop",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for RunningPolicy {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-project" => Ok(RunningPolicy::PerProject),
+            "refuse" => Ok(RunningPolicy::Refuse),
+            "auto-stop" => Ok(RunningPolicy::AutoStop),
+            _ => Err(Error::InvalidSetting(format!("{}={}", RUNNING_POLICY, s))),
+        }
+    }
+}
+
+pub(crate) fn get(name: &str, conn: &Connection) -> Result<Option<String>, Error> {
+    let value = conn
+        .query_row("SELECT value FROM settings WHERE name=?1", [name], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    Ok(value)
+}
+
+pub(crate) fn set(name: &str, value: &str, conn: &Connection) -> Result<(), Error> {
+    conn.execute(
+        "INSERT INTO settings (name, value) VALUES (?1, ?2)
+            ON CONFLICT (name) DO UPDATE SET value=excluded.value",
+        (name, value),
+    )?;
+    Ok(())
+}
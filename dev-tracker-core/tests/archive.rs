@@ -0,0 +1,52 @@
+//! Archiving a project and restoring it again.
+
+use dev_tracker_core::data::DataStore;
+use dev_tracker_core::model::ProjectStatus;
+
+fn status(ds: &DataStore, name: &str) -> ProjectStatus {
+    ds.get_project(name).unwrap().unwrap().status()
+}
+
+fn set_status(ds: &DataStore, name: &str, status: ProjectStatus) {
+    let mut project = ds.get_project(name).unwrap().unwrap();
+    project.set_status(status);
+    ds.update_project(&project).unwrap();
+}
+
+#[test]
+fn unarchive_restores_earlier_status() {
+    let ds = DataStore::new(None).unwrap();
+    ds.create_project("p").unwrap();
+    set_status(&ds, "p", ProjectStatus::Paused);
+    let project = ds.get_project("p").unwrap().unwrap();
+
+    ds.archive_project(&project).unwrap();
+    assert_eq!(status(&ds, "p"), ProjectStatus::Archived);
+
+    // Archiving again must not forget the status from before the first time.
+    ds.archive_project(&project).unwrap();
+    ds.unarchive_project(&project).unwrap();
+    assert_eq!(status(&ds, "p"), ProjectStatus::Paused);
+}
+
+#[test]
+fn unarchive_without_earlier_status_makes_project_active() {
+    let ds = DataStore::new(None).unwrap();
+    ds.create_project("p").unwrap();
+    set_status(&ds, "p", ProjectStatus::Archived);
+    let project = ds.get_project("p").unwrap().unwrap();
+
+    ds.unarchive_project(&project).unwrap();
+    assert_eq!(status(&ds, "p"), ProjectStatus::Active);
+}
+
+#[test]
+fn unarchive_leaves_unarchived_project_alone() {
+    let ds = DataStore::new(None).unwrap();
+    ds.create_project("p").unwrap();
+    set_status(&ds, "p", ProjectStatus::Finished);
+    let project = ds.get_project("p").unwrap().unwrap();
+
+    ds.unarchive_project(&project).unwrap();
+    assert_eq!(status(&ds, "p"), ProjectStatus::Finished);
+}
@@ -0,0 +1,133 @@
+//! Recording changes in the history and undoing them.
+
+use std::path::PathBuf;
+
+use chrono::{Duration, TimeZone, Utc};
+use dev_tracker_core::data::DataStore;
+use rusqlite::Connection;
+
+fn project_names(ds: &DataStore) -> Vec<String> {
+    let mut names: Vec<_> = ds
+        .get_projects()
+        .unwrap()
+        .into_iter()
+        .map(|p| p.unwrap().name().to_string())
+        .collect();
+    names.sort();
+    names
+}
+
+#[test]
+fn undo_reverts_an_update() {
+    let ds = DataStore::new(None).unwrap();
+    ds.begin_operation("add").unwrap();
+    ds.create_project("p").unwrap();
+
+    ds.begin_operation("describe").unwrap();
+    let mut project = ds.get_project("p").unwrap().unwrap();
+    project.set_description(Some("about p".to_string()));
+    ds.update_project(&project).unwrap();
+
+    let undone = ds.undo(1).unwrap();
+    assert_eq!(undone.len(), 1);
+    assert_eq!(undone[0].description(), Some("describe"));
+    let project = ds.get_project("p").unwrap().unwrap();
+    assert_eq!(project.description(), None);
+}
+
+#[test]
+fn undo_restores_rows_deleted_by_cascade() {
+    let ds = DataStore::new(None).unwrap();
+    ds.begin_operation("add").unwrap();
+    ds.create_project("p").unwrap();
+    ds.create_activitytype("Code", None).unwrap();
+    let project = ds.get_project("p").unwrap().unwrap();
+    let at = ds.get_activitytype("Code").unwrap().unwrap();
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+    let activity = ds
+        .create_activity(
+            &project,
+            &at,
+            Some("work".to_string()),
+            &["x".to_string()],
+            start,
+            start + Duration::hours(1),
+        )
+        .unwrap();
+    ds.tag_project(&project, &["y".to_string()]).unwrap();
+
+    ds.begin_operation("delete").unwrap();
+    ds.delete_project(project).unwrap();
+    assert!(ds.get_activity_with_id(activity.id()).unwrap().is_none());
+    assert!(ds.get_project("p").unwrap().is_none());
+
+    ds.undo(1).unwrap();
+    let project = ds.get_project("p").unwrap().unwrap();
+    let activity = ds.get_activity_with_id(activity.id()).unwrap().unwrap();
+    assert_eq!(activity.project(), project.id());
+    assert_eq!(activity.description(), Some("work"));
+    let tags: Vec<_> = ds
+        .get_activity_tags(&activity)
+        .unwrap()
+        .iter()
+        .map(|t| t.name().to_string())
+        .collect();
+    assert_eq!(tags, ["x"]);
+    let tags: Vec<_> = ds
+        .get_project_tags(&project)
+        .unwrap()
+        .iter()
+        .map(|t| t.name().to_string())
+        .collect();
+    assert_eq!(tags, ["y"]);
+}
+
+#[test]
+fn undo_several_operations_newest_first() {
+    let ds = DataStore::new(None).unwrap();
+    for name in ["a", "b", "c"] {
+        ds.begin_operation(&format!("add {}", name)).unwrap();
+        ds.create_project(name).unwrap();
+    }
+
+    let undone = ds.undo(2).unwrap();
+    let descriptions: Vec<_> = undone.iter().map(|o| o.description().unwrap()).collect();
+    assert_eq!(descriptions, ["add c", "add b"]);
+    assert_eq!(project_names(&ds), ["a"]);
+
+    // Undone operations stay in the history and are not undone again.
+    let history = ds.get_history(10).unwrap();
+    let undone: Vec<_> = history.iter().map(|o| o.is_undone()).collect();
+    assert_eq!(undone, [true, true, false]);
+    ds.undo(1).unwrap();
+    assert!(project_names(&ds).is_empty());
+}
+
+#[test]
+fn changes_made_by_other_connections_are_not_recorded() {
+    let path: PathBuf =
+        std::env::temp_dir().join(format!("dt-history-{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    {
+        let ds = DataStore::new(Some(&path)).unwrap();
+        ds.begin_operation("add p").unwrap();
+        ds.create_project("p").unwrap();
+
+        let other = Connection::open(&path).unwrap();
+        other
+            .execute(
+                "INSERT INTO projects (name, status, created) VALUES ('other', 'active', ?1)",
+                [Utc::now()],
+            )
+            .unwrap();
+        let history = ds.get_history(10).unwrap();
+        assert_eq!(history.len(), 1);
+        assert_eq!(history[0].changes().len(), 1);
+
+        ds.undo(1).unwrap();
+        assert_eq!(project_names(&ds), ["other"]);
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,50 @@
+//! Merging activities.
+
+use chrono::{Duration, TimeZone, Utc};
+use dev_tracker_core::data::DataStore;
+use dev_tracker_core::Error;
+use rusqlite::Connection;
+
+#[test]
+fn merge_refuses_running_activity_that_is_not_last() {
+    let path = std::env::temp_dir().join(format!("dt-merge-{}.sqlite", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    {
+        let ds = DataStore::new(Some(&path)).unwrap();
+        ds.create_project("p").unwrap();
+        ds.create_activitytype("Code", None).unwrap();
+        let project = ds.get_project("p").unwrap().unwrap();
+        let at = ds.get_activitytype("Code").unwrap().unwrap();
+        let start = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+        let first = ds
+            .create_activity(&project, &at, None, &[], start, start + Duration::hours(1))
+            .unwrap();
+        let second = ds
+            .create_activity(
+                &project,
+                &at,
+                None,
+                &[],
+                start + Duration::hours(2),
+                start + Duration::hours(3),
+            )
+            .unwrap();
+
+        // The DataStore would not record a running activity before another,
+        // but a file edited by hand can have one.
+        Connection::open(&path)
+            .unwrap()
+            .execute("UPDATE activities SET end=NULL WHERE id=?1", [first.id()])
+            .unwrap();
+
+        let result = ds.merge_activities(&[second.clone(), first.clone()]);
+        assert!(matches!(
+            result,
+            Err(Error::ActivityStillRunning(id)) if id == first.id().to_string()
+        ));
+        assert!(ds.get_activity_with_id(second.id()).unwrap().is_some());
+    }
+
+    std::fs::remove_file(&path).unwrap();
+}
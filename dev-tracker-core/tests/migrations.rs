@@ -0,0 +1,233 @@
+//! Upgrading data files made by older versions of dt.
+
+use std::path::PathBuf;
+
+use dev_tracker_core::data::DataStore;
+use dev_tracker_core::migrations::SCHEMA_VERSION;
+use dev_tracker_core::Error;
+use rusqlite::Connection;
+
+// The tables as they were created before the schema had a version.
+const BASELINE: &str = "
+    CREATE TABLE projects (
+        id          INTEGER PRIMARY KEY,
+        name        TEXT NOT NULL
+    );
+    CREATE TABLE activitytypes (
+        id          INTEGER PRIMARY KEY,
+        name        TEXT NOT NULL,
+        description TEXT
+    );
+    CREATE TABLE activities (
+        id          INTEGER PRIMARY KEY,
+        project     INTEGER NOT NULL,
+        atype       INTEGER NOT NULL,
+        description TEXT,
+        start       DATETIME NOT NULL,
+        end         DATETIME
+    );
+    CREATE TABLE repos (
+        id          INTEGER PRIMARY KEY,
+        project     INTEGER NOT NULL,
+        path        TEXT NOT NULL
+    );
+    CREATE TABLE counts (
+        id      INTEGER PRIMARY KEY,
+        repo    INTEGER NOT NULL,
+        date    DATETIME NOT NULL,
+        count   TEXT NOT NULL
+    );
+    INSERT INTO activitytypes (id, name, description)
+        VALUES (0, 'Unknown', 'Unknown activity type');";
+
+// A data file in a directory of its own, which also holds the automatic
+// backups made before upgrading.
+struct File {
+    dir: PathBuf,
+    path: PathBuf,
+}
+
+impl File {
+    // A data file at the baseline schema with `sql` run on it.
+    fn baseline(name: &str, sql: &str) -> Self {
+        let dir =
+            std::env::temp_dir().join(format!("dt-migrations-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir(&dir).unwrap();
+        let path = dir.join("data.sqlite");
+        let conn = Connection::open(&path).unwrap();
+        conn.execute_batch(BASELINE).unwrap();
+        conn.execute_batch(sql).unwrap();
+
+        File { dir, path }
+    }
+
+    fn conn(&self) -> Connection {
+        Connection::open(&self.path).unwrap()
+    }
+
+    fn user_version(&self) -> u32 {
+        self.conn()
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .unwrap()
+    }
+}
+
+impl Drop for File {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[test]
+fn upgrades_baseline_database() {
+    let file = File::baseline(
+        "upgrade",
+        "INSERT INTO projects (id, name) VALUES (1, 'p');
+        INSERT INTO activitytypes (id, name) VALUES (1, 'Code');
+        INSERT INTO activities (id, project, atype, description, start, end)
+            VALUES (1, 1, 1, 'work', '2024-01-01 09:00:00+00:00', '2024-01-01 10:00:00+00:00');
+        INSERT INTO repos (id, project, path) VALUES (1, 1, '/src/p');
+        INSERT INTO counts (id, repo, date, count)
+            VALUES (1, 1, '2024-01-01 10:00:00+00:00', '{}');",
+    );
+    assert_eq!(file.user_version(), 0);
+
+    let ds = DataStore::new(Some(&file.path)).unwrap();
+    assert_eq!(ds.schema_version().unwrap(), SCHEMA_VERSION);
+    assert_eq!(file.user_version(), SCHEMA_VERSION);
+
+    let project = ds.get_project("p").unwrap().unwrap();
+    let activities = ds.get_activities(&project).unwrap();
+    assert_eq!(activities.len(), 1);
+    let activity = activities[0].as_ref().unwrap();
+    assert_eq!(activity.description(), Some("work"));
+    assert_eq!(
+        ds.get_activitytype_with_id(activity.atype())
+            .unwrap()
+            .unwrap()
+            .name(),
+        "Code"
+    );
+    let repos = ds.get_repos(&project).unwrap();
+    assert_eq!(repos.len(), 1);
+    let counts: u32 = file
+        .conn()
+        .query_row("SELECT COUNT(*) FROM counts", [], |row| row.get(0))
+        .unwrap();
+    assert_eq!(counts, 1);
+
+    // The file was backed up before it was upgraded.
+    let backups = std::fs::read_dir(file.dir.join("backups")).unwrap().count();
+    assert_eq!(backups, 1);
+
+    // Opening the upgraded file again changes nothing.
+    drop(ds);
+    let ds = DataStore::new(Some(&file.path)).unwrap();
+    assert!(ds.get_project("p").unwrap().is_some());
+    let backups = std::fs::read_dir(file.dir.join("backups")).unwrap().count();
+    assert_eq!(backups, 1);
+}
+
+#[test]
+fn refuses_newer_schema() {
+    let newer = SCHEMA_VERSION + 1;
+    let file = File::baseline("newer", &format!("PRAGMA user_version = {};", newer));
+
+    assert!(matches!(
+        DataStore::new(Some(&file.path)),
+        Err(Error::SchemaTooNew(version)) if version == newer
+    ));
+    assert_eq!(file.user_version(), newer);
+}
+
+#[test]
+fn failed_upgrade_leaves_file_unchanged() {
+    // A table the pauses migration would create already exists.
+    let file = File::baseline(
+        "failed",
+        "INSERT INTO projects (id, name) VALUES (1, 'p');
+        CREATE TABLE pauses (id INTEGER PRIMARY KEY);",
+    );
+
+    assert!(DataStore::new(Some(&file.path)).is_err());
+    assert_eq!(file.user_version(), 0);
+    let conn = file.conn();
+    let columns: u32 = conn
+        .query_row(
+            "SELECT COUNT(*) FROM pragma_table_info('projects')",
+            [],
+            |row| row.get(0),
+        )
+        .unwrap();
+    assert_eq!(columns, 2);
+}
+
+fn rows(conn: &Connection, sql: &str) -> Vec<(i64, String)> {
+    conn.prepare(sql)
+        .unwrap()
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .collect::<Result<_, _>>()
+        .unwrap()
+}
+
+#[test]
+fn upgrade_cleans_up_rows_constraints_would_reject() {
+    let file = File::baseline(
+        "cleanup",
+        "INSERT INTO projects (id, name) VALUES (1, 'p'), (2, 'p'), (3, 'q');
+        INSERT INTO activitytypes (id, name) VALUES (1, 'Code'), (2, 'Code');
+        INSERT INTO activities (id, project, atype, start, end) VALUES
+            (1, 1, 1, '2024-01-01 09:00:00+00:00', '2024-01-01 10:00:00+00:00'),
+            (2, 99, 1, '2024-01-01 11:00:00+00:00', '2024-01-01 12:00:00+00:00'),
+            (3, 3, 77, '2024-01-01 13:00:00+00:00', '2024-01-01 14:00:00+00:00'),
+            (4, 2, 2, '2024-01-01 15:00:00+00:00', '2024-01-01 16:00:00+00:00');
+        INSERT INTO repos (id, project, path) VALUES (1, 1, '/a'), (2, 1, '/a'), (3, 99, '/b');
+        INSERT INTO counts (id, repo, date, count) VALUES
+            (1, 2, '2024-01-01 10:00:00+00:00', '{}'),
+            (2, 3, '2024-01-01 10:00:00+00:00', '{}'),
+            (3, 50, '2024-01-01 10:00:00+00:00', '{}');",
+    );
+
+    drop(DataStore::new(Some(&file.path)).unwrap());
+    let conn = file.conn();
+
+    // Duplicate names get their ID appended.
+    assert_eq!(
+        rows(&conn, "SELECT id, name FROM projects ORDER BY id"),
+        [(1, "p".into()), (2, "p (2)".into()), (3, "q".into())]
+    );
+    assert_eq!(
+        rows(&conn, "SELECT id, name FROM activitytypes ORDER BY id"),
+        [
+            (0, "Unknown".into()),
+            (1, "Code".into()),
+            (2, "Code (2)".into())
+        ]
+    );
+
+    // The activity of a missing project is deleted, the one with a missing
+    // activity type becomes Unknown.
+    assert_eq!(
+        rows(
+            &conn,
+            "SELECT id, project || ' ' || atype FROM activities ORDER BY id"
+        ),
+        [(1, "1 1".into()), (3, "3 0".into()), (4, "2 2".into())]
+    );
+
+    // The duplicate repo's counts move to the oldest one, repos of missing
+    // projects and counts of missing repos are deleted.
+    assert_eq!(
+        rows(&conn, "SELECT id, path FROM repos ORDER BY id"),
+        [(1, "/a".into())]
+    );
+    assert_eq!(
+        rows(
+            &conn,
+            "SELECT id, CAST(repo AS TEXT) FROM counts ORDER BY id"
+        ),
+        [(1, "1".into())]
+    );
+}
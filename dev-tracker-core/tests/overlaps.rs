@@ -0,0 +1,221 @@
+//! Resolving overlapping activities keeps their tags, descriptions and
+//! pauses.
+//!
+//! The DataStore refuses to record overlapping activities in a project, so
+//! each test moves an activity with SQL to make one, as an older version of
+//! dt could have.
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use dev_tracker_core::data::DataStore;
+use dev_tracker_core::model::Activity;
+use dev_tracker_core::overlap::OverlapResolution;
+use dev_tracker_core::Error;
+use rusqlite::Connection;
+
+struct Fixture {
+    ds: DataStore,
+    conn: Connection,
+    path: PathBuf,
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+fn at(hours: f64) -> DateTime<Utc> {
+    Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap() + Duration::minutes((hours * 60.0) as i64)
+}
+
+impl Fixture {
+    fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!(
+            "dt-overlaps-{}-{}.sqlite",
+            name,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let ds = DataStore::new(Some(&path)).unwrap();
+        ds.create_project("p").unwrap();
+        ds.create_activitytype("Code", None).unwrap();
+        ds.create_activitytype("Review", None).unwrap();
+        let conn = Connection::open(&path).unwrap();
+
+        Fixture { ds, conn, path }
+    }
+
+    fn add(&self, atype: &str, description: &str, tag: &str, start: f64, end: f64) -> u64 {
+        let project = self.ds.get_project("p").unwrap().unwrap();
+        let activity_type = self.ds.get_activitytype(atype).unwrap().unwrap();
+        // Added well away from the others, then moved into place.
+        let activity = self
+            .ds
+            .create_activity(
+                &project,
+                &activity_type,
+                Some(description.to_string()),
+                &[tag.to_string()],
+                at_far(start),
+                at_far(end),
+            )
+            .unwrap();
+        let id = activity.id();
+        self.conn
+            .execute(
+                "UPDATE activities SET start=?2, end=?3 WHERE id=?1",
+                (id, at(start), at(end)),
+            )
+            .unwrap();
+        id
+    }
+
+    fn pause(&self, activity: u64, start: f64, end: f64) {
+        self.conn
+            .execute(
+                "INSERT INTO pauses (activity, start, end) VALUES (?1, ?2, ?3)",
+                (activity, at(start), at(end)),
+            )
+            .unwrap();
+    }
+
+    fn resolve(&self, resolution: OverlapResolution) -> Result<Option<Activity>, Error> {
+        let overlaps = self.ds.find_overlaps(None).unwrap();
+        assert_eq!(overlaps.len(), 1);
+        self.ds.resolve_overlap(&overlaps[0], resolution)
+    }
+
+    fn activity(&self, id: u64) -> Option<Activity> {
+        self.ds.get_activity_with_id(id).unwrap()
+    }
+
+    fn tags(&self, activity: &Activity) -> Vec<String> {
+        self.ds
+            .get_activity_tags(activity)
+            .unwrap()
+            .iter()
+            .map(|t| t.name().to_string())
+            .collect()
+    }
+
+    fn pauses(&self, activity: &Activity) -> Vec<(DateTime<Utc>, Option<DateTime<Utc>>)> {
+        self.ds
+            .get_pauses(activity)
+            .unwrap()
+            .iter()
+            .map(|p| (p.start_time(), p.end_time()))
+            .collect()
+    }
+}
+
+// Well after the times used by the tests, so activities can be added there
+// without overlapping.
+fn at_far(hours: f64) -> DateTime<Utc> {
+    at(hours + 1000.0)
+}
+
+#[test]
+fn trim_keeps_both_activities_details() {
+    let f = Fixture::new("trim");
+    let first = f.add("Code", "dup1", "x", 0.0, 6.0);
+    let second = f.add("Code", "dup2", "important", 3.0, 5.0);
+    f.pause(first, 1.0, 2.0);
+    f.pause(first, 4.0, 4.5);
+    f.pause(second, 4.0, 4.5);
+
+    assert!(f.resolve(OverlapResolution::Trim).unwrap().is_none());
+
+    let first = f.activity(first).unwrap();
+    assert_eq!(first.end_time(), Some(at(3.0)));
+    assert_eq!(first.description(), Some("dup1"));
+    assert_eq!(f.tags(&first), ["x"]);
+    assert_eq!(f.pauses(&first), [(at(1.0), Some(at(2.0)))]);
+
+    let second = f.activity(second).unwrap();
+    assert_eq!(second.description(), Some("dup2"));
+    assert_eq!(f.tags(&second), ["important"]);
+    assert_eq!(f.pauses(&second), [(at(4.0), Some(at(4.5)))]);
+}
+
+#[test]
+fn merge_moves_tags_descriptions_and_pauses() {
+    let f = Fixture::new("merge");
+    let first = f.add("Code", "dup1", "x", 0.0, 6.0);
+    let second = f.add("Code", "dup2", "important", 3.0, 8.0);
+    f.pause(first, 1.0, 2.0);
+    f.pause(second, 7.0, 7.5);
+
+    assert!(f.resolve(OverlapResolution::Merge).unwrap().is_none());
+
+    assert!(f.activity(second).is_none());
+    let first = f.activity(first).unwrap();
+    assert_eq!(first.start_time(), at(0.0));
+    assert_eq!(first.end_time(), Some(at(8.0)));
+    assert_eq!(first.description(), Some("dup1; dup2"));
+    assert_eq!(f.tags(&first), ["important", "x"]);
+    assert_eq!(
+        f.pauses(&first),
+        [(at(1.0), Some(at(2.0))), (at(7.0), Some(at(7.5)))]
+    );
+}
+
+#[test]
+fn merge_refuses_different_activity_types() {
+    let f = Fixture::new("merge-types");
+    let first = f.add("Code", "dup1", "x", 0.0, 6.0);
+    let second = f.add("Review", "dup2", "important", 3.0, 5.0);
+
+    assert!(matches!(
+        f.resolve(OverlapResolution::Merge),
+        Err(Error::ActivitiesOfDifferentTypes(_))
+    ));
+    assert_eq!(f.activity(first).unwrap().end_time(), Some(at(6.0)));
+    assert!(f.activity(second).is_some());
+}
+
+#[test]
+fn merge_refuses_extending_over_another_activity() {
+    let f = Fixture::new("merge-extend");
+    let first = f.add("Code", "dup1", "x", 0.0, 4.0);
+    let second = f.add("Code", "dup2", "important", 3.0, 8.0);
+    // Only overlaps the second activity, until the first is extended.
+    let third = f.add("Code", "other", "y", 6.0, 9.0);
+
+    let overlaps = f.ds.find_overlaps(None).unwrap();
+    let overlap = overlaps.iter().find(|o| o.first().id() == first).unwrap();
+    assert!(matches!(
+        f.ds.resolve_overlap(overlap, OverlapResolution::Merge),
+        Err(Error::ActivityOverlaps(id)) if id == third.to_string()
+    ));
+    assert!(f.activity(second).is_some());
+    assert_eq!(f.tags(&f.activity(second).unwrap()), ["important"]);
+}
+
+#[test]
+fn split_copies_tags_and_moves_pauses() {
+    let f = Fixture::new("split");
+    let first = f.add("Code", "dup1", "x", 0.0, 6.0);
+    let second = f.add("Code", "dup2", "important", 3.0, 5.0);
+    f.pause(first, 1.0, 2.0);
+    f.pause(first, 5.25, 5.5);
+
+    let rest = f.resolve(OverlapResolution::Split).unwrap().unwrap();
+
+    let first = f.activity(first).unwrap();
+    assert_eq!(first.end_time(), Some(at(3.0)));
+    assert_eq!(first.description(), Some("dup1"));
+    assert_eq!(f.tags(&first), ["x"]);
+    assert_eq!(f.pauses(&first), [(at(1.0), Some(at(2.0)))]);
+
+    assert_eq!(rest.start_time(), at(5.0));
+    assert_eq!(rest.end_time(), Some(at(6.0)));
+    assert_eq!(rest.description(), Some("dup1"));
+    assert_eq!(f.tags(&rest), ["x"]);
+    assert_eq!(f.pauses(&rest), [(at(5.25), Some(at(5.5)))]);
+
+    let second = f.activity(second).unwrap();
+    assert_eq!(second.description(), Some("dup2"));
+    assert_eq!(f.tags(&second), ["important"]);
+}
@@ -0,0 +1,22 @@
+//! Reading reports written as JSON by `dt generate json`.
+
+use dev_tracker_core::report::Report;
+
+#[test]
+fn reads_report_from_before_tags() {
+    let json = r#"{
+        "start": "2024-01-01T00:00:00Z",
+        "end": null,
+        "project_name": "p",
+        "project_description": null,
+        "activities": [
+            {"name": "Code", "start": "2024-01-01T09:00:00Z", "minutes": 60}
+        ],
+        "counts": {}
+    }"#;
+
+    let report: Report = serde_json::from_str(json).unwrap();
+    let again = serde_json::to_value(&report).unwrap();
+    assert_eq!(again["tags"], serde_json::json!({}));
+    assert_eq!(again["activities"][0]["tags"], serde_json::json!([]));
+}
@@ -0,0 +1,58 @@
+//! Times report generation over a large table of activities, run with
+//! `cargo test --release -p dev-tracker-core -- --ignored --nocapture`.
+//!
+//! Activities are filtered in SQL, so the time taken should grow with the
+//! number of activities in the report rather than the number in the table.
+
+use std::time::{Duration as StdDuration, Instant};
+
+use chrono::{Duration, TimeZone, Utc};
+use dev_tracker_core::data::DataStore;
+
+const ACTIVITIES: i64 = 50_000;
+const RESULT_SIZES: [i64; 4] = [100, 1_000, 10_000, 50_000];
+const RUNS: u32 = 3;
+
+#[test]
+#[ignore]
+fn report_time_scales_with_result_size() {
+    let ds = DataStore::new(None).unwrap();
+    ds.create_project("bench").unwrap();
+    ds.create_activitytype("Bench", None).unwrap();
+    let project = ds.get_project("bench").unwrap().unwrap();
+    let at = ds.get_activitytype("Bench").unwrap().unwrap();
+
+    // One hour of activity every two hours, added newest first so that the
+    // overlap check for each one only finds the activities that start before
+    // it ends, which keeps the setup quick.
+    let base = Utc.with_ymd_and_hms(2020, 1, 1, 0, 0, 0).unwrap();
+    let setup = Instant::now();
+    for i in (0..ACTIVITIES).rev() {
+        let start = base + Duration::hours(2 * i);
+        ds.create_activity(&project, &at, None, &[], start, start + Duration::hours(1))
+            .unwrap();
+    }
+    println!("created {} activities in {:?}", ACTIVITIES, setup.elapsed());
+
+    let mut timings = Vec::new();
+    for size in RESULT_SIZES {
+        let end = base + Duration::hours(2 * (size - 1));
+        let mut best = StdDuration::MAX;
+        for _ in 0..RUNS {
+            let started = Instant::now();
+            let report = ds
+                .create_report(&project, Some(base), Some(end), None)
+                .unwrap();
+            best = best.min(started.elapsed());
+
+            let json = serde_json::to_value(&report).unwrap();
+            assert_eq!(json["activities"].as_array().unwrap().len() as i64, size);
+        }
+        println!("report of {:>6} activities took {:?}", size, best);
+        timings.push(best);
+    }
+
+    // The smallest report reads a five hundredth of the activities that the
+    // largest does, allow plenty of headroom for noise.
+    assert!(timings[0] * 10 < timings[timings.len() - 1]);
+}
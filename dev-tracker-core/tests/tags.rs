@@ -0,0 +1,108 @@
+//! Tagging activities and projects.
+
+use chrono::{Duration, TimeZone, Utc};
+use dev_tracker_core::data::DataStore;
+use dev_tracker_core::model::Activity;
+use dev_tracker_core::Error;
+
+fn tags(names: &[&str]) -> Vec<String> {
+    names.iter().map(|n| n.to_string()).collect()
+}
+
+fn setup() -> (DataStore, Activity) {
+    let ds = DataStore::new(None).unwrap();
+    ds.create_project("p").unwrap();
+    ds.create_activitytype("Code", None).unwrap();
+    let project = ds.get_project("p").unwrap().unwrap();
+    let at = ds.get_activitytype("Code").unwrap().unwrap();
+    let start = Utc.with_ymd_and_hms(2024, 1, 1, 9, 0, 0).unwrap();
+    let activity = ds
+        .create_activity(&project, &at, None, &[], start, start + Duration::hours(1))
+        .unwrap();
+
+    (ds, activity)
+}
+
+fn activity_tags(ds: &DataStore, activity: &Activity) -> Vec<String> {
+    ds.get_activity_tags(activity)
+        .unwrap()
+        .iter()
+        .map(|t| t.name().to_string())
+        .collect()
+}
+
+#[test]
+fn invalid_tag_adds_none() {
+    let (ds, activity) = setup();
+
+    let result = ds.tag_activity(&activity, &tags(&["good", "bad tag"]));
+    assert!(matches!(result, Err(Error::InvalidTag(tag)) if tag == "bad tag"));
+    assert!(activity_tags(&ds, &activity).is_empty());
+    assert!(ds.get_tags().unwrap().is_empty());
+
+    let project = ds.get_project("p").unwrap().unwrap();
+    let result = ds.tag_project(&project, &tags(&["good", "x,y"]));
+    assert!(matches!(result, Err(Error::InvalidTag(_))));
+    assert!(ds.get_project_tags(&project).unwrap().is_empty());
+}
+
+#[test]
+fn missing_tag_removes_none() {
+    let (ds, activity) = setup();
+    ds.tag_activity(&activity, &tags(&["a", "b"])).unwrap();
+
+    let result = ds.untag_activity(&activity, &tags(&["a", "missing"]));
+    assert!(matches!(result, Err(Error::TagNotFound(tag)) if tag == "missing"));
+    assert_eq!(activity_tags(&ds, &activity), ["a", "b"]);
+
+    ds.untag_activity(&activity, &tags(&["a", "b"])).unwrap();
+    assert!(activity_tags(&ds, &activity).is_empty());
+    assert!(ds.get_tags().unwrap().is_empty());
+}
+
+#[test]
+fn tags_for_several_activities() {
+    let (ds, first) = setup();
+    let project = ds.get_project("p").unwrap().unwrap();
+    let at = ds.get_activitytype("Code").unwrap().unwrap();
+    let start = first.end_time().unwrap();
+    let second = ds
+        .create_activity(&project, &at, None, &[], start, start + Duration::hours(1))
+        .unwrap();
+    ds.tag_activity(&first, &tags(&["b", "a"])).unwrap();
+
+    let found = ds.get_tags_for_activities(&[first, second]).unwrap();
+    assert_eq!(found.len(), 1);
+    let names: Vec<_> = found
+        .values()
+        .next()
+        .unwrap()
+        .iter()
+        .map(|t| t.name())
+        .collect();
+    assert_eq!(names, ["a", "b"]);
+}
+
+#[test]
+fn invalid_tag_starts_or_adds_no_activity() {
+    let (ds, _) = setup();
+    let project = ds.get_project("p").unwrap().unwrap();
+    let at = ds.get_activitytype("Code").unwrap().unwrap();
+
+    let result = ds.start_activity(&project, &at, None, &tags(&["good", "bad tag"]));
+    assert!(matches!(result, Err(Error::InvalidTag(tag)) if tag == "bad tag"));
+
+    let start = Utc.with_ymd_and_hms(2024, 1, 2, 9, 0, 0).unwrap();
+    let result = ds.create_activity(
+        &project,
+        &at,
+        None,
+        &tags(&["good", "x,y"]),
+        start,
+        start + Duration::hours(1),
+    );
+    assert!(matches!(result, Err(Error::InvalidTag(tag)) if tag == "x,y"));
+
+    assert_eq!(ds.get_activities(&project).unwrap().len(), 1);
+    assert!(ds.get_tags().unwrap().is_empty());
+}